@@ -0,0 +1,168 @@
+use crate::{
+    ArcWriteGuard, LockResult, LockState, MrwLock, ReadGuard, Reobtained, Source, WriteGuard,
+};
+use std::{
+    ops::Deref,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Read guard returned by [MrwLock::read_arc], holding an [Arc] to the lock instead of borrowing it
+/// so it is `'static` and can be moved into threads and tasks. Otherwise the same as [ReadGuard].
+/// `U` is the type the guard points to, which differs from the locked `T` for [ArcSliceReadGuard]
+/// ```
+/// use manual_rwlock::MrwLock;
+/// use std::sync::Arc;
+/// let mrw_lock = Arc::new(MrwLock::new(10));
+/// let read = mrw_lock.read_arc().unwrap();
+/// let handle = std::thread::spawn(move || {
+///     let mut write = read.to_write().unwrap();
+///     *write += 1;
+/// });
+/// handle.join().unwrap();
+/// assert_eq!(*mrw_lock.read().unwrap(), 11);
+/// ```
+pub struct ArcReadGuard<T: ?Sized, U: ?Sized = T> {
+    // Declared before `lock` so the read lock is released before the lock can be freed
+    pub(super) guard: ReadGuard<'static, U>,
+    pub(super) lock: Arc<MrwLock<T>>,
+}
+
+/// Slice read guard returned by [MrwLock::read_slice_arc], see [SliceReadGuard](crate::SliceReadGuard)
+pub type ArcSliceReadGuard<T, U> = ArcReadGuard<T, [U]>;
+
+impl<T: ?Sized, U: ?Sized> ArcReadGuard<T, U> {
+    /// # Safety
+    /// a read lock must be held on `lock`, it is released when the guard is dropped
+    pub(crate) unsafe fn from_locked(
+        lock: Arc<MrwLock<T>>,
+        data: *mut U,
+        source: Option<Source<U>>,
+    ) -> Self {
+        let guard = ReadGuard::new(&*(&lock.state as *const LockState), data, source);
+        // Made to be moved between threads, so never counted as held by one, see [MrwLock::with_level]
+        guard.held.send();
+        ArcReadGuard { guard, lock }
+    }
+
+    /// Rewrap the result of converting the borrowed guard
+    fn wrap(
+        res: LockResult<WriteGuard<'static, U>, ReadGuard<'static, U>>,
+        lock: Arc<MrwLock<T>>,
+    ) -> LockResult<ArcWriteGuard<T, U>, Self> {
+        match res {
+            Ok(guard) => Ok(ArcWriteGuard { guard, lock }),
+            Err(e) => Err(e.map(
+                lock,
+                |guard, lock| ArcWriteGuard { guard, lock },
+                |guard, lock| ArcReadGuard { guard, lock },
+            )),
+        }
+    }
+
+    /// The lock this guard keeps alive
+    pub fn lock(&self) -> &Arc<MrwLock<T>> {
+        &self.lock
+    }
+
+    /// See [ReadGuard::try_to_write]
+    pub fn try_to_write(self) -> LockResult<ArcWriteGuard<T, U>, Self> {
+        let ArcReadGuard { guard, lock } = self;
+        Self::wrap(guard.try_to_write(), lock)
+    }
+
+    /// See [ReadGuard::to_write]
+    pub fn to_write(self) -> LockResult<ArcWriteGuard<T, U>, Self> {
+        let ArcReadGuard { guard, lock } = self;
+        Self::wrap(guard.to_write(), lock)
+    }
+
+    /// See [ReadGuard::to_write_for]
+    pub fn to_write_for(self, timeout: Duration) -> LockResult<ArcWriteGuard<T, U>, Self> {
+        let ArcReadGuard { guard, lock } = self;
+        Self::wrap(guard.to_write_for(timeout), lock)
+    }
+
+    /// See [ReadGuard::to_write_until]
+    pub fn to_write_until(self, deadline: Instant) -> LockResult<ArcWriteGuard<T, U>, Self> {
+        let ArcReadGuard { guard, lock } = self;
+        Self::wrap(guard.to_write_until(deadline), lock)
+    }
+
+    /// See [ReadGuard::to_write_async]
+    pub async fn to_write_async(self) -> LockResult<ArcWriteGuard<T, U>, Self> {
+        let ArcReadGuard { guard, lock } = self;
+        Self::wrap(guard.to_write_async().await, lock)
+    }
+
+    /// See [ReadGuard::unlocked]
+    pub fn unlocked<R>(&mut self, f: impl FnOnce() -> R) -> (R, Reobtained) {
+        self.guard.unlocked(f)
+    }
+
+    /// See [ReadGuard::unlocked_fair]
+    pub fn unlocked_fair<R>(&mut self, f: impl FnOnce() -> R) -> (R, Reobtained) {
+        self.guard.unlocked_fair(f)
+    }
+
+    /// See [ReadGuard::early_release]
+    /// # Safety
+    /// Do not access contents before reobtaining lock with either reobtain or try_reobtain
+    pub unsafe fn early_release(&self) {
+        self.guard.early_release();
+    }
+
+    /// See [ReadGuard::reobtain]
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain(&self) -> LockResult<Reobtained> {
+        self.guard.reobtain()
+    }
+
+    /// See [ReadGuard::reobtain_for]
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain_for(&self, timeout: Duration) -> LockResult<Reobtained> {
+        self.guard.reobtain_for(timeout)
+    }
+
+    /// See [ReadGuard::reobtain_until]
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain_until(&self, deadline: Instant) -> LockResult<Reobtained> {
+        self.guard.reobtain_until(deadline)
+    }
+
+    /// See [ReadGuard::reobtain_async]
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub async unsafe fn reobtain_async(&self) -> LockResult<Reobtained> {
+        self.guard.reobtain_async().await
+    }
+
+    /// See [ReadGuard::try_reobtain]
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn try_reobtain(&self) -> LockResult<Reobtained> {
+        self.guard.try_reobtain()
+    }
+}
+
+impl<T: ?Sized, U: ?Sized> Deref for ArcReadGuard<T, U> {
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized, U: ?Sized> Clone for ArcReadGuard<T, U> {
+    fn clone(&self) -> Self {
+        let guard = self.guard.clone();
+        guard.held.send();
+        ArcReadGuard {
+            guard,
+            lock: self.lock.clone(),
+        }
+    }
+}
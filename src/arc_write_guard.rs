@@ -0,0 +1,125 @@
+use crate::{ArcReadGuard, LockResult, LockState, MrwLock, Reobtained, Source, WriteGuard};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Write guard returned by [MrwLock::write_arc], holding an [Arc] to the lock instead of borrowing it.
+/// Otherwise the same as [WriteGuard]
+pub struct ArcWriteGuard<T: ?Sized, U: ?Sized = T> {
+    // Declared before `lock` so the write lock is released before the lock can be freed
+    pub(super) guard: WriteGuard<'static, U>,
+    pub(super) lock: Arc<MrwLock<T>>,
+}
+
+/// Slice write guard returned by [MrwLock::write_slice_arc], see [SliceWriteGuard](crate::SliceWriteGuard)
+/// ```
+/// use manual_rwlock::MrwLock;
+/// use std::sync::Arc;
+/// let mrw_lock = Arc::new(MrwLock::new(vec![1, 2, 3]));
+/// let mut write = mrw_lock.write_slice_arc().unwrap();
+/// std::thread::spawn(move || write[0] = 4).join().unwrap();
+/// assert_eq!(*mrw_lock.read().unwrap(), [4, 2, 3]);
+/// ```
+pub type ArcSliceWriteGuard<T, U> = ArcWriteGuard<T, [U]>;
+
+impl<T: ?Sized, U: ?Sized> ArcWriteGuard<T, U> {
+    /// # Safety
+    /// a write lock must be held on `lock`, it is released when the guard is dropped
+    pub(crate) unsafe fn from_locked(
+        lock: Arc<MrwLock<T>>,
+        data: *mut U,
+        source: Option<Source<U>>,
+    ) -> Self {
+        let guard = WriteGuard::new(&*(&lock.state as *const LockState), data, source);
+        // Made to be moved between threads, so never counted as held by one, see [MrwLock::with_level]
+        guard.held.send();
+        ArcWriteGuard { guard, lock }
+    }
+
+    /// The lock this guard keeps alive
+    pub fn lock(&self) -> &Arc<MrwLock<T>> {
+        &self.lock
+    }
+
+    /// See [WriteGuard::to_read]
+    pub fn to_read(self) -> ArcReadGuard<T, U> {
+        let ArcWriteGuard { guard, lock } = self;
+        ArcReadGuard {
+            guard: guard.to_read(),
+            lock,
+        }
+    }
+
+    /// See [WriteGuard::poison]
+    pub fn poison(&self, reason: impl Into<Arc<str>>) {
+        self.guard.poison(reason);
+    }
+
+    /// See [WriteGuard::unlocked]
+    pub fn unlocked<R>(&mut self, f: impl FnOnce() -> R) -> (R, Reobtained) {
+        self.guard.unlocked(f)
+    }
+
+    /// See [WriteGuard::unlocked_fair]
+    pub fn unlocked_fair<R>(&mut self, f: impl FnOnce() -> R) -> (R, Reobtained) {
+        self.guard.unlocked_fair(f)
+    }
+
+    /// See [WriteGuard::early_release]
+    /// # Safety
+    /// Do not access contents before reobtaining lock with either reobtain or try_reobtain
+    pub unsafe fn early_release(&self) {
+        self.guard.early_release();
+    }
+
+    /// See [WriteGuard::reobtain]
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain(&self) -> LockResult<Reobtained> {
+        self.guard.reobtain()
+    }
+
+    /// See [WriteGuard::reobtain_for]
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain_for(&self, timeout: Duration) -> LockResult<Reobtained> {
+        self.guard.reobtain_for(timeout)
+    }
+
+    /// See [WriteGuard::reobtain_until]
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain_until(&self, deadline: Instant) -> LockResult<Reobtained> {
+        self.guard.reobtain_until(deadline)
+    }
+
+    /// See [WriteGuard::reobtain_async]
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub async unsafe fn reobtain_async(&self) -> LockResult<Reobtained> {
+        self.guard.reobtain_async().await
+    }
+
+    /// See [WriteGuard::try_reobtain]
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn try_reobtain(&self) -> LockResult<Reobtained> {
+        self.guard.try_reobtain()
+    }
+}
+
+impl<T: ?Sized, U: ?Sized> Deref for ArcWriteGuard<T, U> {
+    type Target = U;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized, U: ?Sized> DerefMut for ArcWriteGuard<T, U> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
@@ -0,0 +1,63 @@
+//! Async notification of changes to the data behind a [LockState]
+use crate::{LockState, VERSION_STEP};
+use std::{
+    future::poll_fn,
+    sync::atomic::Ordering::Acquire,
+    task::{Context, Poll},
+};
+
+/// Async iterator yielding the new [LockState::version] each time a write lock is released after modifying the data.
+/// Changes made while the previous one was not yet taken are merged, so only the latest version is yielded.
+/// [Self::poll_next] has the same signature as `Stream::poll_next` so it can be wrapped in a `Stream`, the stream never ends
+#[must_use = "changes are only seen while polling"]
+pub struct Changes<'a> {
+    state: &'a LockState,
+    last_version: u32,
+    /// Key of the registered waker
+    key: Option<u32>,
+}
+
+impl<'a> Changes<'a> {
+    pub(crate) fn new(state: &'a LockState) -> Changes<'a> {
+        Changes {
+            state,
+            last_version: state.version(),
+            key: None,
+        }
+    }
+
+    fn changed(&mut self) -> Option<u32> {
+        let version = self.state.version.load(Acquire) / VERSION_STEP;
+        if version == self.last_version {
+            return None;
+        }
+        self.last_version = version;
+        self.state.change_wakers.remove(&mut self.key);
+        Some(version)
+    }
+
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<u32>> {
+        if let Some(version) = self.changed() {
+            return Poll::Ready(Some(version));
+        }
+        self.state
+            .change_wakers
+            .register(&mut self.key, cx.waker(), &self.state.version);
+        // Changes before registering are seen by checking again, changes after it wake this task
+        match self.changed() {
+            Some(version) => Poll::Ready(Some(version)),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Wait for the next change, returning the new version. Never returns `None`
+    pub async fn next(&mut self) -> Option<u32> {
+        poll_fn(|cx| self.poll_next(cx)).await
+    }
+}
+
+impl Drop for Changes<'_> {
+    fn drop(&mut self) {
+        self.state.change_wakers.remove(&mut self.key);
+    }
+}
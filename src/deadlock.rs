@@ -0,0 +1,432 @@
+//! Deadlock detection across every [LockState](crate::LockState) with the `deadlock_detection` feature.
+//! Locks record which threads hold them and which thread is blocked waiting for what, forming a wait-for graph
+//! that [check_deadlock] searches for cycles. Without the feature nothing is recorded
+#[cfg(feature = "deadlock_detection")]
+pub(crate) use detection::{acquired, converted, forget, released, start_wait, stop_wait};
+#[cfg(feature = "deadlock_detection")]
+pub use detection::{check_deadlock, spawn_checker, DeadlockChecker, DeadlockedThread};
+#[cfg(not(feature = "deadlock_detection"))]
+pub(crate) use disabled::{acquired, converted, released, start_wait, stop_wait};
+
+/// A lock held by a thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Hold {
+    Read,
+    Upgradable,
+    Write,
+}
+
+/// A lock a thread is blocked waiting for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Want {
+    Read,
+    Upgradable,
+    Write,
+    /// Convert the given held lock to a write lock
+    Upgrade(Hold),
+}
+
+#[cfg(feature = "deadlock_detection")]
+mod detection {
+    use super::{Hold, Want};
+    use crate::{LockState, Policy};
+    use std::{
+        backtrace::Backtrace,
+        collections::HashMap,
+        sync::{
+            atomic::{
+                AtomicBool,
+                Ordering::{Acquire, Release},
+            },
+            Arc, Mutex, MutexGuard, OnceLock,
+        },
+        thread::{self, JoinHandle, ThreadId},
+        time::{Duration, Instant},
+    };
+
+    struct Holder {
+        thread: ThreadId,
+        hold: Hold,
+        backtrace: Arc<Backtrace>,
+    }
+
+    struct Waiter {
+        lock: usize,
+        want: Want,
+        /// Whether new readers wait behind waiting writers under the lock's [Policy]
+        readers_queue: bool,
+        name: Option<String>,
+        backtrace: Arc<Backtrace>,
+    }
+
+    #[derive(Default)]
+    struct Registry {
+        /// Holders of each lock, by address
+        holds: HashMap<usize, Vec<Holder>>,
+        waits: HashMap<ThreadId, Waiter>,
+    }
+
+    fn registry() -> MutexGuard<'static, Registry> {
+        static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+        REGISTRY
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn key(state: &LockState) -> usize {
+        state as *const LockState as usize
+    }
+
+    /// Where a lock was obtained or waited for. Only captured when enabled with `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE`
+    fn backtrace() -> Arc<Backtrace> {
+        Arc::new(Backtrace::capture())
+    }
+
+    pub(crate) fn acquired(state: &LockState, hold: Hold) {
+        let holder = Holder {
+            thread: thread::current().id(),
+            hold,
+            backtrace: backtrace(),
+        };
+        registry().holds.entry(key(state)).or_default().push(holder);
+    }
+
+    /// Find a `hold` on the lock, preferring one obtained by this thread as guards can be sent to other threads
+    fn find(holders: &[Holder], hold: Hold) -> Option<usize> {
+        let thread = thread::current().id();
+        let mut found = None;
+        for (i, holder) in holders.iter().enumerate() {
+            if holder.hold == hold {
+                if holder.thread == thread {
+                    return Some(i);
+                }
+                found.get_or_insert(i);
+            }
+        }
+        found
+    }
+
+    pub(crate) fn released(state: &LockState, hold: Hold) {
+        let mut registry = registry();
+        let key = key(state);
+        let Some(holders) = registry.holds.get_mut(&key) else {
+            return;
+        };
+        if let Some(i) = find(holders, hold) {
+            holders.swap_remove(i);
+        }
+        if holders.is_empty() {
+            registry.holds.remove(&key);
+        }
+    }
+
+    pub(crate) fn converted(state: &LockState, from: Hold, to: Hold) {
+        let backtrace = backtrace();
+        let mut registry = registry();
+        let holders = registry.holds.entry(key(state)).or_default();
+        match find(holders, from) {
+            Some(i) => {
+                holders[i].hold = to;
+                holders[i].backtrace = backtrace;
+            }
+            None => holders.push(Holder {
+                thread: thread::current().id(),
+                hold: to,
+                backtrace,
+            }),
+        }
+    }
+
+    pub(crate) fn start_wait(state: &LockState, want: Want) {
+        let thread = thread::current();
+        let waiter = Waiter {
+            lock: key(state),
+            want,
+            // Under Fifo everyone waits their turn, including readers behind a writer
+            readers_queue: state.writers_block_readers() || state.policy() == Policy::Fifo,
+            name: thread.name().map(String::from),
+            backtrace: backtrace(),
+        };
+        registry().waits.insert(thread.id(), waiter);
+    }
+
+    pub(crate) fn stop_wait() {
+        registry().waits.remove(&thread::current().id());
+    }
+
+    /// Remove a lock that is being dropped, so a new lock at the same address does not inherit its holders
+    pub(crate) fn forget(state: &LockState) {
+        registry().holds.remove(&key(state));
+    }
+
+    /// Threads that `waiter` on `thread` is waiting for, with where they obtained or waited for the lock
+    fn blockers(
+        registry: &Registry,
+        thread: ThreadId,
+        waiter: &Waiter,
+    ) -> Vec<(ThreadId, Arc<Backtrace>)> {
+        let mut blockers = Vec::new();
+        // An upgrading thread does not wait for the lock it is converting
+        let mut own = match waiter.want {
+            Want::Upgrade(from) => Some(from),
+            _ => None,
+        };
+        for holder in registry.holds.get(&waiter.lock).into_iter().flatten() {
+            if holder.thread == thread && own == Some(holder.hold) {
+                own = None;
+                continue;
+            }
+            let blocks = match waiter.want {
+                Want::Read => holder.hold == Hold::Write,
+                Want::Upgradable => holder.hold != Hold::Read,
+                Want::Write | Want::Upgrade(_) => true,
+            };
+            if blocks {
+                blockers.push((holder.thread, holder.backtrace.clone()));
+            }
+        }
+        if matches!(waiter.want, Want::Read | Want::Upgradable) {
+            // New readers wait behind an upgrade, and behind waiting writers with some policies
+            for (&other, queued) in &registry.waits {
+                let blocks = match queued.want {
+                    Want::Upgrade(_) => true,
+                    Want::Write => waiter.readers_queue,
+                    _ => false,
+                };
+                if queued.lock == waiter.lock && other != thread && blocks {
+                    blockers.push((other, queued.backtrace.clone()));
+                }
+            }
+        }
+        blockers
+    }
+
+    /// A thread in a deadlock, see [check_deadlock]
+    #[derive(Debug)]
+    pub struct DeadlockedThread {
+        thread_id: ThreadId,
+        name: Option<String>,
+        backtrace: Arc<Backtrace>,
+        held_backtrace: Arc<Backtrace>,
+    }
+
+    impl DeadlockedThread {
+        pub fn thread_id(&self) -> ThreadId {
+            self.thread_id
+        }
+
+        pub fn thread_name(&self) -> Option<&str> {
+            self.name.as_deref()
+        }
+
+        /// Where the thread is blocked waiting for a lock
+        pub fn backtrace(&self) -> &Backtrace {
+            &self.backtrace
+        }
+
+        /// Where the thread obtained, or started waiting for, the lock the previous thread in the cycle is waiting for
+        pub fn held_backtrace(&self) -> &Backtrace {
+            &self.held_backtrace
+        }
+    }
+
+    /// Find threads blocked on each other's locks, returning each cycle found.
+    /// Each thread in a cycle is waiting for a lock held by the next, and the last by the first.
+    /// Upgrades wait for the other readers, and a thread holding a lock it waits for is a cycle on its own.
+    /// Locks are attributed to the thread that obtained them, even if the guard was sent to another thread.
+    /// Async tasks are not blocked threads so are only seen holding locks.
+    /// Backtraces are captured when enabled by `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE`, as with [Backtrace::capture]
+    /// ```
+    /// use manual_rwlock::{check_deadlock, MrwLock};
+    /// use std::{sync::Arc, thread, time::Duration};
+    /// let lock = Arc::new(MrwLock::new(0));
+    /// let spawned = lock.clone();
+    /// thread::spawn(move || {
+    ///     let read = spawned.read().unwrap();
+    ///     // Waits for its own read lock
+    ///     let _write = read.clone().to_write();
+    /// });
+    /// let deadlocks = loop {
+    ///     let deadlocks = check_deadlock();
+    ///     if !deadlocks.is_empty() {
+    ///         break deadlocks;
+    ///     }
+    ///     thread::sleep(Duration::from_millis(1));
+    /// };
+    /// assert_eq!(deadlocks[0].len(), 1);
+    /// ```
+    pub fn check_deadlock() -> Vec<Vec<DeadlockedThread>> {
+        let registry = registry();
+        let threads: Vec<ThreadId> = registry.waits.keys().copied().collect();
+        let index: HashMap<ThreadId, usize> =
+            threads.iter().enumerate().map(|(i, &t)| (t, i)).collect();
+        // Only waiting threads can be in a cycle
+        let edges: Vec<Vec<(usize, Arc<Backtrace>)>> = threads
+            .iter()
+            .map(|thread| {
+                blockers(&registry, *thread, &registry.waits[thread])
+                    .into_iter()
+                    .filter_map(|(blocker, backtrace)| Some((*index.get(&blocker)?, backtrace)))
+                    .collect()
+            })
+            .collect();
+        let mut cycles = Vec::new();
+        for start in 0..threads.len() {
+            find_cycles(start, &edges, &mut Vec::new(), &mut cycles);
+        }
+        cycles
+            .into_iter()
+            .map(|cycle| {
+                (0..cycle.len())
+                    .map(|i| {
+                        let (thread, _) = cycle[i];
+                        let (_, held) = &cycle[(i + cycle.len() - 1) % cycle.len()];
+                        let waiter = &registry.waits[&threads[thread]];
+                        DeadlockedThread {
+                            thread_id: threads[thread],
+                            name: waiter.name.clone(),
+                            backtrace: waiter.backtrace.clone(),
+                            held_backtrace: held.clone(),
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Depth first search from `node` for paths leading back to the first thread of `path`, recording each as a cycle.
+    /// Only threads after the first are followed, so each cycle is found once starting from its first thread,
+    /// and threads are only skipped while on the path so cycles sharing threads are all found.
+    /// Each entry of a cycle is a thread and where the next thread obtained the lock it waits for
+    fn find_cycles(
+        node: usize,
+        edges: &[Vec<(usize, Arc<Backtrace>)>],
+        path: &mut Vec<(usize, Arc<Backtrace>)>,
+        cycles: &mut Vec<Vec<(usize, Arc<Backtrace>)>>,
+    ) {
+        let start = path.first().map_or(node, |&(first, _)| first);
+        for (i, (next, backtrace)) in edges[node].iter().enumerate() {
+            // A thread can be blocked by several locks held by the same thread
+            if edges[node][..i].iter().any(|(other, _)| other == next) {
+                continue;
+            }
+            path.push((node, backtrace.clone()));
+            if *next == start {
+                cycles.push(path.clone());
+            } else if *next > start && !path.iter().any(|(thread, _)| thread == next) {
+                find_cycles(*next, edges, path, cycles);
+            }
+            path.pop();
+        }
+    }
+
+    /// Spawn a thread calling [check_deadlock] every `interval`, passing each cycle to `on_deadlock` once when it is first found.
+    /// The thread runs until the returned [DeadlockChecker] is dropped
+    /// ```
+    /// use manual_rwlock::{spawn_checker, MrwLock};
+    /// use std::{sync::{mpsc, Arc}, thread, time::Duration};
+    /// let (tx, rx) = mpsc::channel();
+    /// let checker = spawn_checker(Duration::from_millis(1), move |cycle| tx.send(cycle.len()).unwrap());
+    /// let lock = Arc::new(MrwLock::new(0));
+    /// thread::spawn(move || {
+    ///     let read = lock.read().unwrap();
+    ///     let _write = read.clone().to_write();
+    /// });
+    /// assert_eq!(rx.recv().unwrap(), 1);
+    /// drop(checker);
+    /// ```
+    pub fn spawn_checker(
+        interval: Duration,
+        on_deadlock: impl Fn(Vec<DeadlockedThread>) + Send + 'static,
+    ) -> DeadlockChecker {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = thread::Builder::new()
+            .name("deadlock checker".into())
+            .spawn(move || {
+                let mut reported: Vec<Vec<ThreadId>> = Vec::new();
+                loop {
+                    // Parked rather than sleeping so dropping the checker wakes it
+                    let next = crate::deadline(interval);
+                    loop {
+                        if stopped.load(Acquire) {
+                            return;
+                        }
+                        match next.map(|next| next.checked_duration_since(Instant::now())) {
+                            Some(None) => break,
+                            Some(Some(timeout)) => thread::park_timeout(timeout),
+                            None => thread::park(),
+                        }
+                    }
+                    let cycles = check_deadlock();
+                    let threads = |cycle: &[DeadlockedThread]| -> Vec<ThreadId> {
+                        cycle.iter().map(DeadlockedThread::thread_id).collect()
+                    };
+                    let found: Vec<Vec<ThreadId>> =
+                        cycles.iter().map(|cycle| threads(cycle)).collect();
+                    for cycle in cycles {
+                        let ids = threads(&cycle);
+                        let same = |old: &Vec<ThreadId>| {
+                            old.len() == ids.len() && ids.iter().all(|id| old.contains(id))
+                        };
+                        if !reported.iter().any(same) {
+                            on_deadlock(cycle);
+                        }
+                    }
+                    reported = found;
+                }
+            })
+            .expect("failed to spawn deadlock checker");
+        DeadlockChecker {
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// The thread started by [spawn_checker]. Dropping it stops the thread, waiting for a check in progress to finish
+    #[must_use = "the checker stops when dropped, call `detach` to keep it running"]
+    pub struct DeadlockChecker {
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl DeadlockChecker {
+        /// Leave the thread checking until the process exits
+        pub fn detach(mut self) {
+            self.thread = None;
+        }
+    }
+
+    impl Drop for DeadlockChecker {
+        fn drop(&mut self) {
+            if let Some(thread) = self.thread.take() {
+                self.stop.store(true, Release);
+                thread.thread().unpark();
+                // A panic in `on_deadlock` already stopped it
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "deadlock_detection"))]
+mod disabled {
+    use super::{Hold, Want};
+    use crate::LockState;
+
+    #[inline(always)]
+    pub(crate) fn acquired(_state: &LockState, _hold: Hold) {}
+
+    #[inline(always)]
+    pub(crate) fn released(_state: &LockState, _hold: Hold) {}
+
+    #[inline(always)]
+    pub(crate) fn converted(_state: &LockState, _from: Hold, _to: Hold) {}
+
+    #[inline(always)]
+    pub(crate) fn start_wait(_state: &LockState, _want: Want) {}
+
+    #[inline(always)]
+    pub(crate) fn stop_wait() {}
+}
@@ -0,0 +1,155 @@
+use std::{error::Error, fmt, sync::Arc};
+
+/// `G` is the guard obtained when the lock is [LockError::Poisoned].
+/// `R` is the guard handed back when converting it fails with [LockError::WouldBlock], [LockError::TimedOut]
+/// or [LockError::UpgradeConflict], still holding its lock
+pub enum LockError<G = (), R = ()> {
+    TooManyReaders,
+    WouldBlock(R),
+    /// The lock was obtained, but a thread panicked while holding a write lock.
+    /// The guard can be recovered from the [PoisonError]
+    Poisoned(PoisonError<G>),
+    /// The lock could not be obtained before the timeout or deadline
+    TimedOut(R),
+    /// Another reader is already upgrading to a write lock, or an [UpgradableReadGuard](crate::UpgradableReadGuard) is held.
+    /// Waiting would deadlock, so the read guard is handed back to be released
+    UpgradeConflict(R),
+}
+pub type LockResult<Gaurd, R = ()> = Result<Gaurd, LockError<Gaurd, R>>;
+
+impl LockError {
+    /// Hand `guard` back in any error that carries one, otherwise it is dropped.
+    /// Poisoned results must be wrapped with [with_guard] instead as the lock was obtained
+    pub(crate) fn hand_back<G, R>(self, guard: R) -> LockError<G, R> {
+        match self {
+            LockError::TooManyReaders => LockError::TooManyReaders,
+            LockError::WouldBlock(()) => LockError::WouldBlock(guard),
+            LockError::Poisoned(_) => unreachable!("poisoned locks are obtained"),
+            LockError::TimedOut(()) => LockError::TimedOut(guard),
+            LockError::UpgradeConflict(()) => LockError::UpgradeConflict(guard),
+        }
+    }
+}
+
+impl<G, R> LockError<G, R> {
+    /// Map the guard held by the error with `g` or `r`, whichever is called is given `ctx`
+    pub(crate) fn map<C, H, S>(
+        self,
+        ctx: C,
+        g: impl FnOnce(G, C) -> H,
+        r: impl FnOnce(R, C) -> S,
+    ) -> LockError<H, S> {
+        match self {
+            LockError::TooManyReaders => LockError::TooManyReaders,
+            LockError::WouldBlock(guard) => LockError::WouldBlock(r(guard, ctx)),
+            LockError::Poisoned(err) => LockError::Poisoned(err.map(|guard| g(guard, ctx))),
+            LockError::TimedOut(guard) => LockError::TimedOut(r(guard, ctx)),
+            LockError::UpgradeConflict(guard) => LockError::UpgradeConflict(r(guard, ctx)),
+        }
+    }
+}
+
+/// Build the guard for a state level acquisition result, attaching it to the error if the lock was poisoned
+pub(crate) fn with_guard<G>(res: LockResult<()>, guard: impl FnOnce() -> G) -> LockResult<G> {
+    match res {
+        Ok(()) => Ok(guard()),
+        Err(LockError::Poisoned(err)) => Err(LockError::Poisoned(err.replace(guard()))),
+        Err(e) => Err(e.hand_back(())),
+    }
+}
+
+impl<G, R> From<PoisonError<G>> for LockError<G, R> {
+    fn from(err: PoisonError<G>) -> Self {
+        LockError::Poisoned(err)
+    }
+}
+
+impl<G, R> fmt::Debug for LockError<G, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::TooManyReaders => f.write_str("TooManyReaders"),
+            LockError::WouldBlock(_) => f.write_str("WouldBlock(..)"),
+            LockError::Poisoned(err) => f.debug_tuple("Poisoned").field(err).finish(),
+            LockError::TimedOut(_) => f.write_str("TimedOut(..)"),
+            LockError::UpgradeConflict(_) => f.write_str("UpgradeConflict(..)"),
+        }
+    }
+}
+
+impl<G, R> fmt::Display for LockError<G, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::TooManyReaders => f.write_str("too many readers"),
+            LockError::WouldBlock(_) => f.write_str("lock would block"),
+            LockError::Poisoned(err) => err.fmt(f),
+            LockError::TimedOut(_) => f.write_str("timed out waiting for lock"),
+            LockError::UpgradeConflict(_) => {
+                f.write_str("another reader is already upgrading to a write lock")
+            }
+        }
+    }
+}
+
+impl<G, R> Error for LockError<G, R> {}
+
+/// A lock was obtained but is poisoned, as a thread panicked while holding a write lock or it was poisoned explicitly.
+/// Like [std::sync::PoisonError] the guard can still be used with [Self::into_inner]
+pub struct PoisonError<G> {
+    guard: G,
+    reason: Option<Arc<str>>,
+}
+
+impl<G> PoisonError<G> {
+    pub fn new(guard: G) -> PoisonError<G> {
+        PoisonError::with_reason(guard, None)
+    }
+
+    pub fn with_reason(guard: G, reason: Option<Arc<str>>) -> PoisonError<G> {
+        PoisonError { guard, reason }
+    }
+
+    /// Why the lock was poisoned, see [MrwLock::poison_reason](crate::MrwLock::poison_reason)
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    /// Swap the guard, keeping the reason
+    pub(crate) fn replace<H>(self, guard: H) -> PoisonError<H> {
+        PoisonError::with_reason(guard, self.reason)
+    }
+
+    pub(crate) fn map<H>(self, f: impl FnOnce(G) -> H) -> PoisonError<H> {
+        PoisonError::with_reason(f(self.guard), self.reason)
+    }
+
+    pub fn into_inner(self) -> G {
+        self.guard
+    }
+
+    pub fn get_ref(&self) -> &G {
+        &self.guard
+    }
+
+    pub fn get_mut(&mut self) -> &mut G {
+        &mut self.guard
+    }
+}
+
+impl<G> fmt::Debug for PoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoisonError")
+            .field("reason", &self.reason)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<G> fmt::Display for PoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            Some(reason) => write!(f, "poisoned lock: {reason}"),
+            None => f.write_str("poisoned lock: another task failed inside"),
+        }
+    }
+}
+
+impl<G> Error for PoisonError<G> {}
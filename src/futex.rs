@@ -0,0 +1,73 @@
+//! Futex style waiting on an [AtomicU32], adding timed waits to [atomic_wait]
+use crate::sync::AtomicU32;
+#[cfg(not(feature = "loom"))]
+use std::time::Duration;
+use std::time::Instant;
+
+#[cfg(not(feature = "loom"))]
+pub(crate) use atomic_wait::{wait, wake_all, wake_one};
+#[cfg(feature = "loom")]
+pub(crate) use loom_futex::{wait, wait_timeout, wake_all, wake_one};
+
+/// If the value is `value`, wait until woken up or `deadline` passes.
+/// Returns false without waiting if `deadline` has already passed. Like [wait] this may return spuriously
+pub(crate) fn wait_until(atomic: &AtomicU32, value: u32, deadline: Option<Instant>) -> bool {
+    let Some(deadline) = deadline else {
+        wait(atomic, value);
+        return true;
+    };
+    let now = Instant::now();
+    if now >= deadline {
+        return false;
+    }
+    wait_timeout(atomic, value, deadline - now);
+    true
+}
+
+#[cfg(all(any(target_os = "linux", target_os = "android"), not(feature = "loom")))]
+fn wait_timeout(atomic: &AtomicU32, value: u32, timeout: Duration) {
+    let timeout = libc::timespec {
+        tv_sec: timeout.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as _,
+    };
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            atomic as *const AtomicU32,
+            libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+            value,
+            &timeout as *const libc::timespec,
+        );
+    }
+}
+
+/// Without a timed futex sleep in short steps, rechecking the value in between
+#[cfg(not(any(target_os = "linux", target_os = "android", feature = "loom")))]
+fn wait_timeout(atomic: &AtomicU32, value: u32, timeout: Duration) {
+    use std::sync::atomic::Ordering::Relaxed;
+    if atomic.load(Relaxed) == value {
+        std::thread::sleep(timeout.min(Duration::from_millis(1)));
+    }
+}
+
+/// loom can not park a thread until woken, so waiting yields to the other modelled threads
+/// and returns as a spurious wake up would. Waking is then not needed
+#[cfg(feature = "loom")]
+mod loom_futex {
+    use crate::sync::{yield_now, AtomicU32};
+    use std::{sync::atomic::Ordering::Relaxed, time::Duration};
+
+    pub(crate) fn wait(atomic: &AtomicU32, value: u32) {
+        if atomic.load(Relaxed) == value {
+            yield_now();
+        }
+    }
+
+    pub(crate) fn wait_timeout(atomic: &AtomicU32, value: u32, _timeout: Duration) {
+        wait(atomic, value);
+    }
+
+    pub(crate) fn wake_one(_atomic: &AtomicU32) {}
+
+    pub(crate) fn wake_all(_atomic: &AtomicU32) {}
+}
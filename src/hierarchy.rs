@@ -0,0 +1,188 @@
+//! Lock hierarchy levels, see [MrwLock::with_level](crate::MrwLock::with_level).
+//! Only checked in debug builds, in release builds nothing is recorded and every call compiles away
+#[cfg(debug_assertions)]
+pub(crate) use checked::{check, forget, violation, Held};
+#[cfg(not(debug_assertions))]
+pub(crate) use unchecked::{check, violation, Held};
+
+#[cfg(debug_assertions)]
+mod checked {
+    use crate::LockState;
+    use std::{
+        sync::{
+            atomic::{AtomicU64, Ordering::Relaxed},
+            Mutex, MutexGuard,
+        },
+        thread::{self, ThreadId},
+    };
+
+    /// A lock with a level held by a thread, `id` is the [Held] of the guard holding it
+    struct Record {
+        id: u64,
+        thread: ThreadId,
+        lock: usize,
+        level: u32,
+    }
+
+    /// Every held lock with a level. Kept in one place rather than per thread so a guard released on another thread
+    /// still removes its record
+    fn records() -> MutexGuard<'static, Vec<Record>> {
+        static RECORDS: Mutex<Vec<Record>> = Mutex::new(Vec::new());
+        RECORDS.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn key(state: &LockState) -> usize {
+        state as *const LockState as usize
+    }
+
+    /// Locking `level` while already holding `highest`
+    pub(crate) struct Violation {
+        level: u32,
+        highest: u32,
+    }
+
+    impl Violation {
+        pub(crate) fn panic(self) -> ! {
+            let Violation { level, highest } = self;
+            panic!("lock hierarchy violated: locking level {level} while holding level {highest}");
+        }
+    }
+
+    /// Whether the current thread holds a lock at the same or a higher level than `state`
+    pub(crate) fn violation(state: &LockState) -> Option<Violation> {
+        let level = state.level?;
+        let thread = thread::current().id();
+        let highest = records()
+            .iter()
+            .filter(|record| record.thread == thread)
+            .map(|record| record.level)
+            .max()?;
+        (highest >= level).then_some(Violation { level, highest })
+    }
+
+    /// Panic on a [Violation], unless the thread is already panicking as a second panic would abort
+    pub(crate) fn check(state: &LockState) {
+        if thread::panicking() {
+            return;
+        }
+        if let Some(violation) = violation(state) {
+            violation.panic();
+        }
+    }
+
+    /// Remove records of a dropped lock, left behind by forgotten guards
+    pub(crate) fn forget(state: &LockState) {
+        if state.level.is_some() {
+            let key = key(state);
+            records().retain(|record| record.lock != key);
+        }
+    }
+
+    /// Nothing recorded
+    const NONE: u64 = 0;
+    /// Never recorded, see [Held::send]
+    const SENT: u64 = u64::MAX;
+
+    /// A guard's hold on a lock with a level, recorded against the thread that obtained it
+    /// and removed when the guard is dropped, on whichever thread that happens
+    pub(crate) struct Held(AtomicU64);
+
+    impl Held {
+        /// Record a lock just obtained on `state` by the current thread
+        pub(crate) fn new(state: &LockState) -> Held {
+            let held = Held(AtomicU64::new(NONE));
+            held.record(state);
+            held
+        }
+
+        /// Record the lock again once it is reobtained
+        pub(crate) fn record(&self, state: &LockState) {
+            let Some(level) = state.level else { return };
+            if self.0.load(Relaxed) == SENT {
+                return;
+            }
+            static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+            let id = NEXT_ID.fetch_add(1, Relaxed);
+            records().push(Record {
+                id,
+                thread: thread::current().id(),
+                lock: key(state),
+                level,
+            });
+            self.0.store(id, Relaxed);
+        }
+
+        /// Remove the record as the lock is released
+        pub(crate) fn release(&self) {
+            let id = self.0.load(Relaxed);
+            if id != NONE && id != SENT {
+                self.0.store(NONE, Relaxed);
+                records().retain(|record| record.id != id);
+            }
+        }
+
+        /// Stop counting the lock as held by any thread, for guards made to be sent to other threads.
+        /// A thread can not tell when a guard is moved away from it, so these are never checked against
+        pub(crate) fn send(&self) {
+            self.release();
+            self.0.store(SENT, Relaxed);
+        }
+
+        /// Move the record into a guard converted from this one
+        pub(crate) fn take(&self) -> Held {
+            Held(AtomicU64::new(self.0.swap(NONE, Relaxed)))
+        }
+    }
+
+    impl Drop for Held {
+        fn drop(&mut self) {
+            self.release();
+        }
+    }
+}
+
+#[cfg(not(debug_assertions))]
+mod unchecked {
+    use crate::LockState;
+
+    /// Never constructed, nothing is checked
+    pub(crate) enum Violation {}
+
+    impl Violation {
+        pub(crate) fn panic(self) -> ! {
+            match self {}
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn violation(_state: &LockState) -> Option<Violation> {
+        None
+    }
+
+    #[inline(always)]
+    pub(crate) fn check(_state: &LockState) {}
+
+    /// Nothing is recorded
+    pub(crate) struct Held;
+
+    impl Held {
+        #[inline(always)]
+        pub(crate) fn new(_state: &LockState) -> Held {
+            Held
+        }
+
+        #[inline(always)]
+        pub(crate) fn record(&self, _state: &LockState) {}
+
+        #[inline(always)]
+        pub(crate) fn release(&self) {}
+
+        #[inline(always)]
+        pub(crate) fn send(&self) {}
+
+        #[inline(always)]
+        pub(crate) fn take(&self) -> Held {
+            Held
+        }
+    }
+}
@@ -1,3 +1,5 @@
+// The doc examples use loom primitives outside a model, which panics, so none are collected under the `loom` feature
+#![cfg(not(all(doctest, feature = "loom")))]
 //! # Manual RwLock
 //! A library implementing An RW lock with more manual control
 //! Sorry for poor documentation, I will update this later.
@@ -40,281 +42,1724 @@
 //!
 //!     
 //!
+mod arc_read_guard;
+mod arc_write_guard;
+mod changes;
+mod deadlock;
+mod error;
+mod futex;
+mod hierarchy;
+mod lock_future;
+#[cfg(all(test, feature = "loom"))]
+mod loom_tests;
+mod mapped_read_guard;
+mod mapped_write_guard;
+mod poison;
+mod policy;
 mod read_guard;
+mod released_read;
+mod released_write;
+mod reobtained;
+mod seq;
+mod seq_cell;
 mod slice_read_guard;
 mod slice_write_guard;
-#[cfg(test)]
+mod stats;
+mod sync;
+#[cfg(all(test, not(feature = "loom")))]
 mod tests;
+mod upgradable_read_guard;
+mod wait_strategy;
+mod wakers;
 mod write_guard;
 
-use atomic_wait::wait;
 use std::{
-    borrow::BorrowMut,
+    alloc::{self, Layout},
+    borrow::{Borrow, BorrowMut},
     cell::UnsafeCell,
-    sync::atomic::{
-        AtomicBool, AtomicU32,
-        Ordering::{Acquire, Relaxed},
+    ptr,
+    sync::{
+        atomic::Ordering::{AcqRel, Acquire, Relaxed, Release},
+        Arc,
     },
     thread,
+    time::{Duration, Instant},
 };
 
+use deadlock::{Hold, Want};
+use error::with_guard;
+use policy::{Phases, Tickets};
+use seq::Seq;
+use stats::{Stats, WaitStart};
+use sync::{loom_const_fn, spin_loop, AtomicBool, AtomicU32, Mutex};
+use wait_strategy::Backoff;
+use wakers::Wakers;
+
+pub use arc_read_guard::{ArcReadGuard, ArcSliceReadGuard};
+pub use arc_write_guard::{ArcSliceWriteGuard, ArcWriteGuard};
+pub use changes::Changes;
+#[cfg(feature = "deadlock_detection")]
+pub use deadlock::{check_deadlock, spawn_checker, DeadlockChecker, DeadlockedThread};
+pub use error::{LockError, LockResult, PoisonError};
+pub use lock_future::LockFuture;
+pub use mapped_read_guard::MappedReadGuard;
+pub use mapped_write_guard::MappedWriteGuard;
+pub use poison::install_poison_hook;
+pub use policy::Policy;
 pub use read_guard::ReadGuard;
+pub use released_read::ReleasedRead;
+pub use released_write::ReleasedWrite;
+pub use reobtained::Reobtained;
+pub use seq_cell::SeqCell;
 pub use slice_read_guard::SliceReadGuard;
 pub use slice_write_guard::SliceWriteGuard;
+#[cfg(feature = "stats")]
+pub use stats::LockStats;
+pub use upgradable_read_guard::UpgradableReadGuard;
+pub use wait_strategy::WaitStrategy;
 pub use write_guard::WriteGuard;
 
-#[derive(Debug)]
-pub enum LockError {
-    TooManyReaders,
-    WouldBlock,
-    Poisoned,
+/// Deadline `timeout` from now, or no deadline if that can not be represented
+pub(crate) fn deadline(timeout: Duration) -> Option<Instant> {
+    Instant::now().checked_add(timeout)
 }
-pub type LockResult<Gaurd> = Result<Gaurd, LockError>;
+
+/// Bits of the state word holding the number of readers. All bits set means write locked
+const READERS: u32 = (1 << 28) - 1;
+const WRITE_LOCKED: u32 = READERS;
+const MAX_READERS: u32 = READERS - 1;
+/// Set while async tasks are waiting, releasing the lock then wakes them, see [LockFuture]
+const ASYNC_WAITING: u32 = 1 << 28;
+/// Set while at least one writer is blocked waiting for the lock
+const WRITER_WAITING: u32 = 1 << 29;
+/// Set while an [UpgradableReadGuard] is held, it is also counted as a reader
+const UPGRADABLE: u32 = 1 << 30;
+/// Set while an [UpgradableReadGuard] waits to upgrade, blocks new readers
+const UPGRADING: u32 = 1 << 31;
+
+/// Set in [LockState::version] while async tasks wait for a change, see [Changes]
+const CHANGE_WAITING: u32 = 1;
+/// The version is counted above [CHANGE_WAITING]
+const VERSION_STEP: u32 = 2;
+
+/// Set in [LockState::served] while a thread waits for a hand off, see [LockState::wait_for_handoff]
+const HANDOFF_WAITING: u32 = 1;
+/// Served waiters are counted above [HANDOFF_WAITING]
+const SERVED_STEP: u32 = 2;
+/// Attempts [MrwLock::optimistic_read] makes before taking a read lock
+const OPTIMISTIC_TRIES: u32 = 4;
 
 /// State that manages control flow for [MrwLock] and Guards: [SliceReadGaurd], [ReadGaurd] ,[SliceWriteGaurd], [WriteGaurd]
 /// Passing by reference allows interior mutability
 pub struct LockState {
     state: AtomicU32,
+    /// Incremented to wake writers waiting for all locks to be released
+    writer_wake: AtomicU32,
+    /// Incremented to wake readers waiting in [LockState::to_write] for the other readers to leave
+    upgrade_wake: AtomicU32,
+    /// Number of writers blocked in [LockState::write], [WRITER_WAITING] is set while this is non zero
+    writers_waiting: AtomicU32,
     poisoned: AtomicBool,
+    /// Why the lock was poisoned, only written while holding the lock on it
+    poison_reason: Mutex<Option<Arc<str>>>,
+    policy: Policy,
+    wait_strategy: WaitStrategy,
+    tickets: Tickets,
+    phases: Phases,
+    wakers: Wakers,
+    /// Incremented by [VERSION_STEP] when a write lock is released after the data was modified
+    version: AtomicU32,
+    /// Whether the data was modified under the current write lock
+    modified: AtomicBool,
+    /// Odd while the data is being modified under a write lock, see [MrwLock::optimistic_read]
+    seq: Seq,
+    change_wakers: Wakers,
+    /// Number of threads and tasks waiting for a lock
+    waiting: AtomicU32,
+    /// Incremented by [SERVED_STEP] when a thread or task stops waiting for a lock
+    served: AtomicU32,
+    stats: Stats,
+    /// See [MrwLock::with_level]
+    level: Option<u32>,
 }
 
 impl LockState {
-    ///Creates new lock state
-    pub const fn new() -> LockState {
-        LockState {
-            state: AtomicU32::new(0),
-            poisoned: AtomicBool::new(false),
+    loom_const_fn! {
+        ///Creates new reader preferring lock state
+        pub fn new() -> LockState {
+            LockState::with_policy(Policy::ReaderPreferred)
         }
     }
 
+    loom_const_fn! {
+        ///Creates new lock state using the given [Policy]
+        pub fn with_policy(policy: Policy) -> LockState {
+            MrwLockBuilder::new().policy(policy).build_state()
+        }
+    }
+
+    loom_const_fn! {
+        fn from_builder(builder: MrwLockBuilder) -> LockState {
+            LockState {
+                state: AtomicU32::new(0),
+                writer_wake: AtomicU32::new(0),
+                upgrade_wake: AtomicU32::new(0),
+                writers_waiting: AtomicU32::new(0),
+                poisoned: AtomicBool::new(false),
+                poison_reason: Mutex::new(None),
+                policy: builder.policy,
+                wait_strategy: builder.wait_strategy,
+                tickets: Tickets::new(),
+                phases: Phases::new(),
+                wakers: Wakers::new(ASYNC_WAITING),
+                version: AtomicU32::new(0),
+                modified: AtomicBool::new(false),
+                seq: Seq::new(),
+                change_wakers: Wakers::new(CHANGE_WAITING),
+                waiting: AtomicU32::new(0),
+                served: AtomicU32::new(0),
+                stats: Stats::new(),
+                level: builder.level,
+            }
+        }
+    }
+
+    pub fn policy(&self) -> Policy {
+        self.policy
+    }
+
+    ///Whether a thread panicked while holding a write lock, or the lock was poisoned with [Self::poison]
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Relaxed)
+    }
+
+    ///Mark the lock poisoned, recording `reason`. Should only be called while holding a write lock
+    pub fn poison(&self, reason: impl Into<Arc<str>>) {
+        let mut poison_reason = self.poison_reason.lock().unwrap_or_else(|e| e.into_inner());
+        *poison_reason = Some(reason.into());
+        self.poisoned.store(true, Relaxed);
+    }
+
+    /// Poison the lock as a write lock is dropped by a panic, keeping a reason already given with [Self::poison]
+    fn poison_by_panic(&self) {
+        let mut poison_reason = self.poison_reason.lock().unwrap_or_else(|e| e.into_inner());
+        poison_reason.get_or_insert_with(poison::panic_message);
+        self.poisoned.store(true, Relaxed);
+    }
+
+    ///Why the lock was poisoned, `None` if not poisoned. If poisoned by a panic, the panic message once [install_poison_hook] is called
+    pub fn poison_reason(&self) -> Option<Arc<str>> {
+        if !self.is_poisoned() {
+            return None;
+        }
+        self.poison_reason
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    ///Clear the poisoned flag and reason, for use once the data has been recovered
+    pub fn clear_poison(&self) {
+        let mut poison_reason = self.poison_reason.lock().unwrap_or_else(|e| e.into_inner());
+        *poison_reason = None;
+        self.poisoned.store(false, Relaxed);
+    }
+
+    /// Level in the lock hierarchy, see [MrwLock::with_level]
+    pub fn level(&self) -> Option<u32> {
+        self.level
+    }
+
+    pub fn wait_strategy(&self) -> WaitStrategy {
+        self.wait_strategy
+    }
+
+    fn wake_one(&self, atomic: &AtomicU32) {
+        if self.wait_strategy.parks() {
+            futex::wake_one(atomic);
+        }
+    }
+
+    fn wake_all(&self, atomic: &AtomicU32) {
+        if self.wait_strategy.parks() {
+            futex::wake_all(atomic);
+        }
+    }
+
+    /// Whether a new reader has to wait given the state word `s`.
+    /// `entitled` readers may pass a waiting writer, see [Policy::PhaseFair]
+    fn blocks_readers(&self, s: u32, entitled: bool) -> bool {
+        s & READERS == WRITE_LOCKED
+            || s & UPGRADING != 0
+            || (!entitled && self.writers_block_readers() && s & WRITER_WAITING != 0)
+    }
+
+    /// Whether a waiting writer keeps new readers out under the lock's [Policy]
+    pub(crate) fn writers_block_readers(&self) -> bool {
+        matches!(self.policy, Policy::WriterPreferred | Policy::PhaseFair)
+    }
+
+    /// Whether readers left over from the last phase must enter before a writer, see [Policy::PhaseFair]
+    fn readers_entitled(&self) -> bool {
+        self.policy == Policy::PhaseFair && self.phases.readers_entitled()
+    }
+
+    fn check_poison(&self) -> LockResult<()> {
+        if self.poisoned.load(Relaxed) {
+            Err(LockError::Poisoned(PoisonError::with_reason(
+                (),
+                self.poison_reason(),
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn wake_writer(&self) {
+        self.writer_wake.fetch_add(1, Release);
+        self.wake_one(&self.writer_wake);
+    }
+
+    /// Wake async tasks if [ASYNC_WAITING] was set in `s`, the state word before a change that may let them in
+    fn wake_async(&self, s: u32) {
+        if s & ASYNC_WAITING != 0 {
+            self.wakers.wake_all(&self.state);
+        }
+    }
+
+    /// Wake async tasks after a change outside the state word, such as the Fifo ticket being served.
+    /// The read-modify-write orders the change before the tasks check [ASYNC_WAITING]
+    fn notify_async(&self) {
+        self.wake_async(self.state.fetch_or(0, AcqRel));
+    }
+
+    /// Remove a reader registered with [Phases::arrive], waking writers if it was the last entitled reader
+    fn leave_phase(&self, phase: u32) {
+        if self.phases.leave(phase) {
+            self.wake_writer();
+            self.notify_async();
+        }
+    }
+
+    /// Count a thread or task which has to wait for a lock, it must call [Self::stop_waiting] once it is done
+    fn start_waiting(&self) -> WaitStart {
+        self.waiting.fetch_add(1, Relaxed);
+        self.stats.start_wait()
+    }
+
+    /// [Self::start_waiting] for a thread that blocks until it gets `want`, which may deadlock
+    fn start_blocking(&self, want: Want) -> WaitStart {
+        deadlock::start_wait(self, want);
+        self.start_waiting()
+    }
+
+    fn stop_blocking(&self, start: WaitStart) {
+        deadlock::stop_wait();
+        self.stop_waiting(start);
+    }
+
+    /// Stop counting a waiting thread or task, whether it obtained the lock or gave up
+    fn stop_waiting(&self, start: WaitStart) {
+        self.stats.stop_wait(start);
+        self.waiting.fetch_sub(1, Relaxed);
+        if self.served.fetch_add(SERVED_STEP, Release) & HANDOFF_WAITING != 0 {
+            self.served.fetch_and(!HANDOFF_WAITING, Relaxed);
+            self.wake_all(&self.served);
+        }
+    }
+
+    /// The number of threads and tasks waiting for a lock and how many have been served so far, see [Self::wait_for_handoff]
+    pub(crate) fn handoff(&self) -> (u32, u32) {
+        (
+            self.waiting.load(Relaxed),
+            self.served.load(Acquire) / SERVED_STEP,
+        )
+    }
+
+    /// Block until as many threads and tasks as were waiting at `handoff` have stopped waiting, or none are left waiting.
+    /// Called after releasing a lock so the waiters get it before it is locked again
+    pub(crate) fn wait_for_handoff(&self, (waiting, served): (u32, u32)) {
+        let handed_off = |s: u32| {
+            (s / SERVED_STEP).wrapping_sub(served) >= waiting || self.waiting.load(Relaxed) == 0
+        };
+        let mut backoff = Backoff::new(self.wait_strategy);
+        loop {
+            if handed_off(self.served.load(Acquire)) {
+                return;
+            }
+            // Waiters served before the bit is set change the count, after it they wake this thread
+            let s = self.served.fetch_or(HANDOFF_WAITING, Acquire) | HANDOFF_WAITING;
+            if handed_off(s) {
+                return;
+            }
+            backoff.wait(&self.served, s, None);
+        }
+    }
+
+    /// Run a blocking acquisition of a new lock, panicking first if it breaks the lock hierarchy
+    fn queued(
+        &self,
+        want: Want,
+        deadline: Option<Instant>,
+        acquire: impl FnOnce() -> LockResult<()>,
+    ) -> LockResult<()> {
+        hierarchy::check(self);
+        self.in_turn(want, deadline, acquire)
+    }
+
+    /// Run a blocking acquisition, waiting for a turn first if the policy is [Policy::Fifo]
+    fn in_turn(
+        &self,
+        want: Want,
+        deadline: Option<Instant>,
+        acquire: impl FnOnce() -> LockResult<()>,
+    ) -> LockResult<()> {
+        if self.policy != Policy::Fifo {
+            return acquire();
+        }
+        deadlock::start_wait(self, want);
+        let turn = self
+            .tickets
+            .wait_turn(deadline, &mut Backoff::new(self.wait_strategy));
+        deadlock::stop_wait();
+        if !turn {
+            self.notify_async();
+            return Err(LockError::TimedOut(()));
+        }
+        let res = acquire();
+        self.tickets.advance();
+        self.notify_async();
+        res
+    }
+
+    /// Take back a lock given up by a guard's `unlocked`, which can not fail as the guard is still in use.
+    /// Poison is ignored and the lock hierarchy is only checked once the lock is held again,
+    /// and not at all if the thread is already panicking as a second panic would abort
+    pub(crate) fn relock(&self, want: Want) {
+        let violation = hierarchy::violation(self);
+        let acquire = || {
+            if want == Want::Read {
+                self.acquire_read(0, None)
+            } else {
+                self.acquire_write(None)
+            }
+        };
+        // Blocking only fails when there are too many readers, wait for some to leave
+        while let Err(LockError::TooManyReaders) = self.in_turn(want, None, acquire) {
+            thread::yield_now();
+        }
+        if let Some(violation) = violation.filter(|_| !thread::panicking()) {
+            violation.panic();
+        }
+    }
+
+    /// Run a non blocking acquisition, failing if the policy is [Policy::Fifo] and others are queued
+    fn try_queued(&self, acquire: impl FnOnce() -> LockResult<()>) -> LockResult<()> {
+        let res = if self.policy != Policy::Fifo {
+            acquire()
+        } else if self.tickets.try_turn() {
+            let res = acquire();
+            self.tickets.advance();
+            self.notify_async();
+            res
+        } else {
+            Err(LockError::WouldBlock(()))
+        };
+        if matches!(res, Err(LockError::WouldBlock(_))) {
+            self.stats.would_block();
+        }
+        res
+    }
+
     ///Increment number of readers. If there is a write lock block thread until read lock can be obtained
     pub fn read(&self) -> LockResult<()> {
+        self.queued(Want::Read, None, || self.acquire_read(0, None))
+    }
+
+    ///Same as [Self::read] but gives up with [LockError::TimedOut] after `timeout`
+    pub fn read_for(&self, timeout: Duration) -> LockResult<()> {
+        let deadline = deadline(timeout);
+        self.queued(Want::Read, deadline, || self.acquire_read(0, deadline))
+    }
+
+    ///Same as [Self::read] but gives up with [LockError::TimedOut] once `deadline` has passed
+    pub fn read_until(&self, deadline: Instant) -> LockResult<()> {
+        self.queued(Want::Read, Some(deadline), || {
+            self.acquire_read(0, Some(deadline))
+        })
+    }
+
+    ///Increment number of readers and mark the lock as having an upgradable reader.
+    /// Blocks while there is a write lock or another upgradable reader
+    pub fn upgradable_read(&self) -> LockResult<()> {
+        self.queued(Want::Upgradable, None, || {
+            self.acquire_read(UPGRADABLE, None)
+        })
+    }
+
+    ///Same as [Self::upgradable_read] but gives up with [LockError::TimedOut] after `timeout`
+    pub fn upgradable_read_for(&self, timeout: Duration) -> LockResult<()> {
+        let deadline = deadline(timeout);
+        self.queued(Want::Upgradable, deadline, || {
+            self.acquire_read(UPGRADABLE, deadline)
+        })
+    }
+
+    ///Same as [Self::upgradable_read] but gives up with [LockError::TimedOut] once `deadline` has passed
+    pub fn upgradable_read_until(&self, deadline: Instant) -> LockResult<()> {
+        self.queued(Want::Upgradable, Some(deadline), || {
+            self.acquire_read(UPGRADABLE, Some(deadline))
+        })
+    }
+
+    /// Add a reader, also setting `flag` which must be 0 or [UPGRADABLE]
+    fn acquire_read(&self, flag: u32, deadline: Option<Instant>) -> LockResult<()> {
         let mut s = self.state.load(Relaxed);
+        // Phase this reader started waiting in, only used by [Policy::PhaseFair]
+        let mut arrival = None;
+        let mut waited = None;
+        let mut backoff = Backoff::new(self.wait_strategy);
+        let res = loop {
+            if let Some(res) = self.attempt_read(flag, &mut arrival, &mut s) {
+                break res;
+            }
+            if waited.is_none() {
+                let want = if flag == UPGRADABLE {
+                    Want::Upgradable
+                } else {
+                    Want::Read
+                };
+                waited = Some(self.start_blocking(want));
+            }
+            if !backoff.wait(&self.state, s, deadline) {
+                break Err(LockError::TimedOut(()));
+            }
+            s = self.state.load(Acquire);
+        };
+        if let Some(phase) = arrival {
+            self.leave_phase(phase);
+        }
+        if let Some(start) = waited {
+            self.stop_blocking(start);
+        }
+        res
+    }
+
+    /// Try to add a reader starting from the state word `s`, returning `None` if it has to wait.
+    /// A waiting reader arrives in the current phase, see [Policy::PhaseFair]
+    fn attempt_read(
+        &self,
+        flag: u32,
+        arrival: &mut Option<u32>,
+        s: &mut u32,
+    ) -> Option<LockResult<()>> {
         loop {
-            if s == u32::MAX {
-                wait(&self.state, u32::MAX);
-                s = self.state.load(Relaxed);
-            } else if s == u32::MAX - 1 {
-                return Err(LockError::TooManyReaders);
-            } else {
-                match self.state.compare_exchange_weak(s, s + 1, Acquire, Relaxed) {
-                    Ok(_) => {
-                        if self.poisoned.load(Relaxed) {
-                            return Err(LockError::Poisoned);
+            let entitled = matches!(*arrival, Some(phase) if self.phases.is_entitled(phase));
+            if self.blocks_readers(*s, entitled) || *s & flag != 0 {
+                if self.policy == Policy::PhaseFair && arrival.is_none() {
+                    *arrival = Some(self.phases.arrive());
+                }
+                return None;
+            } else if *s & READERS == MAX_READERS {
+                return Some(Err(LockError::TooManyReaders));
+            }
+            match self
+                .state
+                .compare_exchange_weak(*s, (*s + 1) | flag, Acquire, Relaxed)
+            {
+                Ok(_) => {
+                    self.stats.read_obtained(flag, *s);
+                    deadlock::acquired(
+                        self,
+                        if flag == UPGRADABLE {
+                            Hold::Upgradable
                         } else {
-                            return Ok(());
-                        }
-                    }
-                    Err(e) => s = e,
+                            Hold::Read
+                        },
+                    );
+                    return Some(self.check_poison());
                 }
+                Err(e) => *s = e,
             }
         }
     }
 
     ///Increment number of readers. If there is a write lock return [LockError::WouldBlock]
     pub fn try_read(&self) -> LockResult<()> {
+        self.try_queued(|| self.try_acquire_read(0))
+    }
+
+    ///Same as [Self::upgradable_read] but returns [LockError::WouldBlock] instead of blocking
+    pub fn try_upgradable_read(&self) -> LockResult<()> {
+        self.try_queued(|| self.try_acquire_read(UPGRADABLE))
+    }
+
+    fn try_acquire_read(&self, flag: u32) -> LockResult<()> {
         let mut s = self.state.load(Relaxed);
         loop {
-            if s < u32::MAX {
-                if s == u32::MAX - 1 {
-                    return Err(LockError::TooManyReaders);
-                }
-                match self.state.compare_exchange_weak(s, s + 1, Acquire, Relaxed) {
-                    Ok(_) => {
-                        if self.poisoned.load(Relaxed) {
-                            return Err(LockError::Poisoned);
+            if self.blocks_readers(s, false) || s & flag != 0 {
+                return Err(LockError::WouldBlock(()));
+            } else if s & READERS == MAX_READERS {
+                return Err(LockError::TooManyReaders);
+            }
+            match self
+                .state
+                .compare_exchange_weak(s, (s + 1) | flag, Acquire, Relaxed)
+            {
+                Ok(_) => {
+                    self.stats.read_obtained(flag, s);
+                    deadlock::acquired(
+                        self,
+                        if flag == UPGRADABLE {
+                            Hold::Upgradable
                         } else {
-                            return Ok(());
-                        }
-                    }
+                            Hold::Read
+                        },
+                    );
+                    return self.check_poison();
+                }
+                Err(e) => s = e,
+            }
+        }
+    }
 
-                    Err(e) => s = e,
+    ///Same as [Self::read] but returns a future that waits without blocking the thread
+    pub fn read_async(&self) -> LockFuture<'_> {
+        hierarchy::check(self);
+        LockFuture::new(self, lock_future::Mode::Read(0))
+    }
+
+    ///Increment number of readers while already holding a read lock, used to clone read guards.
+    /// Never waits, even for a waiting writer, as that writer is already waiting on the held read lock
+    pub fn clone_read(&self) -> LockResult<()> {
+        let mut s = self.state.load(Relaxed);
+        loop {
+            if s & READERS == MAX_READERS {
+                return Err(LockError::TooManyReaders);
+            }
+            match self.state.compare_exchange_weak(s, s + 1, Relaxed, Relaxed) {
+                Ok(_) => {
+                    self.stats.read_obtained(0, s);
+                    deadlock::acquired(self, Hold::Read);
+                    return Ok(());
                 }
-            } else {
-                return Err(LockError::WouldBlock);
+                Err(e) => s = e,
             }
         }
     }
 
     ///Attempt write lock. If there is another lock block thread until the write lock can be obtained
     pub fn write(&self) -> LockResult<()> {
-        while let Err(s) = self.state.compare_exchange(0, u32::MAX, Acquire, Relaxed) {
-            // Wait while already locked.
-            wait(&self.state, s);
+        self.queued(Want::Write, None, || self.acquire_write(None))
+    }
+
+    ///Same as [Self::write] but gives up with [LockError::TimedOut] after `timeout`
+    pub fn write_for(&self, timeout: Duration) -> LockResult<()> {
+        let deadline = deadline(timeout);
+        self.queued(Want::Write, deadline, || self.acquire_write(deadline))
+    }
+
+    ///Same as [Self::write] but gives up with [LockError::TimedOut] once `deadline` has passed
+    pub fn write_until(&self, deadline: Instant) -> LockResult<()> {
+        self.queued(Want::Write, Some(deadline), || {
+            self.acquire_write(Some(deadline))
+        })
+    }
+
+    fn acquire_write(&self, deadline: Option<Instant>) -> LockResult<()> {
+        let mut s = self.state.load(Relaxed);
+        let mut registered = false;
+        let mut timed_out = false;
+        let mut waited = None;
+        let mut backoff = Backoff::new(self.wait_strategy);
+        loop {
+            if self.attempt_write(&mut registered, &mut s) {
+                break;
+            }
+            if waited.is_none() {
+                waited = Some(self.start_blocking(Want::Write));
+            }
+            // Read the wake counter before checking the state again so a release in between is not missed
+            let w = self.writer_wake.load(Acquire);
+            if (self.state.load(Relaxed) & READERS != 0 || self.readers_entitled())
+                && !backoff.wait(&self.writer_wake, w, deadline)
+            {
+                timed_out = true;
+                break;
+            }
+            s = self.state.load(Relaxed);
         }
-        if self.poisoned.load(Relaxed) {
-            Err(LockError::Poisoned)
-        } else {
-            Ok(())
+        if registered {
+            self.unregister_writer();
+        }
+        if let Some(start) = waited {
+            self.stop_blocking(start);
+        }
+        if timed_out {
+            self.writer_gave_up();
+            return Err(LockError::TimedOut(()));
+        }
+        self.check_poison()
+    }
+
+    ///Same as [Self::write] but returns a future that waits without blocking the thread
+    pub fn write_async(&self) -> LockFuture<'_> {
+        hierarchy::check(self);
+        LockFuture::new(self, lock_future::Mode::Write)
+    }
+
+    /// Try to write lock starting from the state word `s`, returning false if it has to wait.
+    /// A waiting writer is registered in [Self::writers_waiting] if the policy blocks readers for it
+    fn attempt_write(&self, registered: &mut bool, s: &mut u32) -> bool {
+        while *s & READERS == 0 && !self.readers_entitled() {
+            match self
+                .state
+                .compare_exchange(*s, *s | WRITE_LOCKED, Acquire, Relaxed)
+            {
+                Ok(_) => {
+                    self.stats.write_obtained();
+                    deadlock::acquired(self, Hold::Write);
+                    return true;
+                }
+                Err(e) => *s = e,
+            }
+        }
+        if matches!(self.policy, Policy::WriterPreferred | Policy::PhaseFair) {
+            if !*registered {
+                self.writers_waiting.fetch_add(1, Relaxed);
+                *registered = true;
+            }
+            if *s & WRITER_WAITING == 0 {
+                self.state.fetch_or(WRITER_WAITING, Relaxed);
+            }
+        }
+        false
+    }
+
+    /// Clean up after a writer stopped waiting without obtaining the lock, it must already be unregistered
+    fn writer_gave_up(&self) {
+        // Readers may be waiting on this writer, and it may have been woken in place of another writer
+        self.wake_all(&self.state);
+        self.wake_writer();
+        self.notify_async();
+    }
+
+    /// Remove a writer from [Self::writers_waiting], clearing [WRITER_WAITING] if it was the last one
+    fn unregister_writer(&self) {
+        if self.writers_waiting.fetch_sub(1, Relaxed) == 1 {
+            self.state.fetch_and(!WRITER_WAITING, Relaxed);
+            // Another writer may have registered between the decrement and clearing the bit
+            if self.writers_waiting.load(Relaxed) != 0 {
+                self.state.fetch_or(WRITER_WAITING, Relaxed);
+            }
         }
     }
 
     ///Attempt write lock. If there is another lock return [LockError::WouldBlock]
     pub fn try_write(&self) -> LockResult<()> {
-        let s = self.state.load(Relaxed);
-        if s == 0 {
-            match self.state.compare_exchange_weak(s, s + 1, Acquire, Relaxed) {
-                Ok(_) => {
-                    if self.poisoned.load(Relaxed) {
-                        Err(LockError::Poisoned)
-                    } else {
-                        Ok(())
+        self.try_queued(|| {
+            let mut s = self.state.load(Relaxed);
+            // Only retry if a flag bit changed, a new lock means this would block
+            while s & READERS == 0 && !self.readers_entitled() {
+                match self
+                    .state
+                    .compare_exchange(s, s | WRITE_LOCKED, Acquire, Relaxed)
+                {
+                    Ok(_) => {
+                        self.stats.write_obtained();
+                        deadlock::acquired(self, Hold::Write);
+                        return self.check_poison();
                     }
+                    Err(e) => s = e,
                 }
-                Err(_) => Err(LockError::WouldBlock),
             }
-        } else {
-            Err(LockError::WouldBlock)
-        }
+            Err(LockError::WouldBlock(()))
+        })
     }
 
-    ///Convert a read lock into a write lock, if there is another lock block thread until write lock can be obtained
+    ///Convert a read lock into a write lock, if there is another lock block thread until write lock can be obtained.
+    /// New readers are blocked while waiting. Only one reader can wait to upgrade at a time, if another reader is
+    /// already upgrading or an upgradable reader is held [LockError::UpgradeConflict] is returned instead of deadlocking
     pub fn to_write(&self) -> LockResult<()> {
-        while let Err(s) = self.state.compare_exchange(1, u32::MAX, Acquire, Relaxed) {
-            // Wait while already locked.
-            wait(&self.state, s);
+        self.to_write_timed(None)
+    }
+
+    ///Same as [Self::to_write] but gives up with [LockError::TimedOut] after `timeout`, keeping the read lock
+    pub fn to_write_for(&self, timeout: Duration) -> LockResult<()> {
+        self.to_write_timed(deadline(timeout))
+    }
+
+    ///Same as [Self::to_write] but gives up with [LockError::TimedOut] once `deadline` has passed, keeping the read lock
+    pub fn to_write_until(&self, deadline: Instant) -> LockResult<()> {
+        self.to_write_timed(Some(deadline))
+    }
+
+    fn to_write_timed(&self, deadline: Option<Instant>) -> LockResult<()> {
+        let mut backoff = Backoff::new(self.wait_strategy);
+        self.claim_upgrade()?;
+        let mut s = self.state.load(Relaxed);
+        let mut waited = None;
+        while !self.attempt_upgrade(&mut s) {
+            if waited.is_none() {
+                waited = Some(self.start_blocking(Want::Upgrade(Hold::Read)));
+            }
+            // Wait until this is the only reader left
+            let u = self.upgrade_wake.load(Acquire);
+            if self.state.load(Relaxed) & READERS != 1
+                && !backoff.wait(&self.upgrade_wake, u, deadline)
+            {
+                if let Some(start) = waited.take() {
+                    self.stop_blocking(start);
+                }
+                self.abandon_upgrade();
+                return Err(LockError::TimedOut(()));
+            }
+            s = self.state.load(Relaxed);
         }
-        if self.poisoned.load(Relaxed) {
-            Err(LockError::Poisoned)
-        } else {
-            Ok(())
+        if let Some(start) = waited {
+            self.stop_blocking(start);
         }
+        self.check_poison()
     }
 
-    ///Attempt to convert a read lock into a write lock, if there is another lock return [LockError::WouldBlock]
-    pub fn try_to_write(&self) -> LockResult<()> {
-        let s = self.state.load(Relaxed);
-        if s == 1 {
+    /// Set [UPGRADING] for a reader about to wait in [Self::to_write], failing if another reader is upgrading
+    fn claim_upgrade(&self) -> LockResult<()> {
+        let mut s = self.state.load(Relaxed);
+        loop {
+            if s & (UPGRADING | UPGRADABLE) != 0 {
+                self.stats.upgrade_failed();
+                return Err(LockError::UpgradeConflict(()));
+            }
             match self
                 .state
-                .compare_exchange_weak(s, u32::MAX, Acquire, Relaxed)
+                .compare_exchange_weak(s, s | UPGRADING, Relaxed, Relaxed)
             {
+                Ok(_) => return Ok(()),
+                Err(e) => s = e,
+            }
+        }
+    }
+
+    /// Convert the claimed upgrade to a write lock starting from the state word `s`, false if other readers are left
+    fn attempt_upgrade(&self, s: &mut u32) -> bool {
+        while *s & READERS == 1 {
+            match self.state.compare_exchange(
+                *s,
+                *s & (WRITER_WAITING | ASYNC_WAITING) | WRITE_LOCKED,
+                Acquire,
+                Relaxed,
+            ) {
                 Ok(_) => {
-                    if self.poisoned.load(Relaxed) {
-                        Err(LockError::Poisoned)
-                    } else {
-                        Ok(())
-                    }
+                    self.stats.upgraded();
+                    deadlock::converted(self, Hold::Read, Hold::Write);
+                    return true;
                 }
-                Err(_) => Err(LockError::WouldBlock),
+                Err(e) => *s = e,
             }
-        } else {
-            Err(LockError::WouldBlock)
         }
+        false
+    }
+
+    /// Stop waiting to upgrade, letting in the readers blocked by it
+    fn abandon_upgrade(&self) {
+        self.stats.upgrade_failed();
+        let s = self.state.fetch_and(!UPGRADING, Relaxed);
+        self.wake_all(&self.state);
+        self.wake_async(s);
+    }
+
+    ///Same as [Self::to_write] but returns a future that waits without blocking the thread.
+    /// Dropping the future stops waiting to upgrade, keeping the read lock
+    pub fn to_write_async(&self) -> LockFuture<'_> {
+        LockFuture::new(self, lock_future::Mode::ToWrite)
+    }
+
+    ///Attempt to convert a read lock into a write lock, if there is another lock return [LockError::WouldBlock]
+    pub fn try_to_write(&self) -> LockResult<()> {
+        let s = self.state.load(Relaxed);
+        if s & READERS == 1
+            && self
+                .state
+                .compare_exchange(s, s | WRITE_LOCKED, Acquire, Relaxed)
+                .is_ok()
+        {
+            self.stats.upgraded();
+            deadlock::converted(self, Hold::Read, Hold::Write);
+            return self.check_poison();
+        }
+        self.stats.upgrade_failed();
+        Err(LockError::WouldBlock(()))
     }
 
-    ///Convert write lock to read lock
+    ///Convert the upgradable read lock into a write lock, blocking new readers and waiting for the existing ones to leave.
+    /// Always succeeds as there can only be one upgradable reader
+    pub fn upgrade(&self) {
+        // Readers can not start upgrading while an upgradable reader is held, so the bit is never already set
+        self.state.fetch_or(UPGRADING, Relaxed);
+        let mut backoff = Backoff::new(self.wait_strategy);
+        let mut s = self.state.load(Relaxed);
+        let mut waited = None;
+        loop {
+            if s & READERS == 1 {
+                match self.state.compare_exchange(
+                    s,
+                    s & (WRITER_WAITING | ASYNC_WAITING) | WRITE_LOCKED,
+                    Acquire,
+                    Relaxed,
+                ) {
+                    Ok(_) => break,
+                    Err(e) => s = e,
+                }
+                continue;
+            }
+            if waited.is_none() {
+                waited = Some(self.start_blocking(Want::Upgrade(Hold::Upgradable)));
+            }
+            let u = self.upgrade_wake.load(Acquire);
+            if self.state.load(Relaxed) & READERS != 1 {
+                backoff.wait(&self.upgrade_wake, u, None);
+            }
+            s = self.state.load(Relaxed);
+        }
+        self.stats.upgraded();
+        deadlock::converted(self, Hold::Upgradable, Hold::Write);
+        if let Some(start) = waited {
+            self.stop_blocking(start);
+        }
+    }
+
+    ///Convert the upgradable read lock into a write lock if it is the only lock, otherwise return [LockError::WouldBlock]
+    pub fn try_upgrade(&self) -> LockResult<()> {
+        let s = self.state.load(Relaxed);
+        if s & READERS == 1
+            && self
+                .state
+                .compare_exchange(
+                    s,
+                    s & (WRITER_WAITING | ASYNC_WAITING) | WRITE_LOCKED,
+                    Acquire,
+                    Relaxed,
+                )
+                .is_ok()
+        {
+            self.stats.upgraded();
+            deadlock::converted(self, Hold::Upgradable, Hold::Write);
+            return Ok(());
+        }
+        self.stats.upgrade_failed();
+        Err(LockError::WouldBlock(()))
+    }
+
+    ///Convert the upgradable read lock into a normal read lock, allowing another upgradable reader
+    pub fn downgrade_upgradable(&self) {
+        deadlock::converted(self, Hold::Upgradable, Hold::Read);
+        let s = self.state.fetch_and(!UPGRADABLE, Release);
+        self.wake_all(&self.state);
+        self.wake_async(s);
+    }
+
+    ///Drop upgradable read lock. Wakes the same waiters as [Self::drop_read] and any waiting upgradable readers
+    pub fn drop_upgradable(&self) {
+        deadlock::released(self, Hold::Upgradable);
+        let s = self.state.fetch_sub(UPGRADABLE + 1, Release);
+        self.stats.read_released(s);
+        self.wake_after_read(s);
+        self.wake_all(&self.state);
+        self.wake_async(s);
+    }
+
+    ///Convert write lock to read lock. Wakes any readers waiting on the write lock
     pub fn to_read(&self) {
-        self.state.store(1, Relaxed);
+        self.stats.write_released(true);
+        deadlock::converted(self, Hold::Write, Hold::Read);
+        let change = self.count_change();
+        if self.policy == Policy::PhaseFair {
+            self.phases.next_phase();
+        }
+        // Only flag bits can change while write locked, so this never retries more than a few times
+        let s = self
+            .state
+            .fetch_update(Release, Relaxed, |s| Some(s & !READERS | 1))
+            .unwrap_or_else(|s| s);
+        self.wake_all(&self.state);
+        self.wake_async(s);
+        self.wake_change(change);
     }
 
-    ///Drop read lock. Decrements the total nubmer of readers
+    ///Drop read lock. Decrements the total nubmer of readers.
+    /// Wakes a writer if this was the last reader, or any upgrading reader if only one reader remains
     pub fn drop_read(&self) {
-        self.state.fetch_sub(1, Relaxed);
+        deadlock::released(self, Hold::Read);
+        let s = self.state.fetch_sub(1, Release);
+        self.stats.read_released(s);
+        self.wake_after_read(s);
+        self.wake_async(s);
     }
 
-    ///Drop write lock. Sets number of readers to 0;
+    /// Wake waiters that can make progress after a reader left, `s` is the state before the reader left
+    fn wake_after_read(&self, s: u32) {
+        match s & READERS {
+            1 => {
+                self.wake_writer();
+            }
+            2 => {
+                self.upgrade_wake.fetch_add(1, Release);
+                self.wake_all(&self.upgrade_wake);
+            }
+            _ => (),
+        }
+    }
+
+    ///Drop write lock. Sets number of readers to 0 and wakes all waiting readers and one waiting writer
     pub fn drop_write(&self) {
         if thread::panicking() {
-            self.poisoned.store(true, Relaxed);
+            self.poison_by_panic();
+        }
+        self.stats.write_released(false);
+        deadlock::released(self, Hold::Write);
+        let change = self.count_change();
+        if self.policy == Policy::PhaseFair {
+            self.phases.next_phase();
+        }
+        let s = self.state.fetch_and(!READERS, Release);
+        self.wake_writer();
+        self.wake_all(&self.state);
+        self.wake_async(s);
+        self.wake_change(change);
+    }
+
+    ///Record that the data was modified under the write lock, so releasing it increments [Self::version].
+    /// Called by the write guards when they are mutably dereferenced, it must be called before modifying the data for [MrwLock::optimistic_read]
+    pub fn mark_modified(&self) {
+        if !self.modified.load(Relaxed) {
+            self.modified.store(true, Relaxed);
+            self.seq.begin_write();
+        }
+    }
+
+    /// [Self::mark_modified] for a modification that may not happen, returning whether the data was not already marked.
+    /// A new mark can be taken back with [Self::unmark_modified]
+    pub(crate) fn try_mark_modified(&self) -> bool {
+        if self.modified.load(Relaxed) {
+            return false;
+        }
+        self.mark_modified();
+        true
+    }
+
+    /// Take back a new mark from [Self::try_mark_modified] once the data was left as it was
+    pub(crate) fn unmark_modified(&self) {
+        self.modified.store(false, Relaxed);
+        self.seq.end_write();
+    }
+
+    ///Number of times a write lock was released after modifying the data, wrapping around
+    pub fn version(&self) -> u32 {
+        self.version.load(Acquire) / VERSION_STEP
+    }
+
+    /// [Self::version] once the held write lock is released, counting the change it will publish
+    pub(crate) fn version_after_write(&self) -> u32 {
+        let step = if self.modified.load(Relaxed) {
+            VERSION_STEP
+        } else {
+            0
+        };
+        self.version.load(Relaxed).wrapping_add(step) / VERSION_STEP
+    }
+
+    /// Increment the version if the data was modified, before the write lock is released so the next holder sees it.
+    /// Returns the old version to pass to [Self::wake_change] once released
+    fn count_change(&self) -> Option<u32> {
+        if !self.modified.swap(false, Relaxed) {
+            return None;
+        }
+        self.seq.end_write();
+        Some(self.version.fetch_add(VERSION_STEP, Release))
+    }
+
+    fn wake_change(&self, change: Option<u32>) {
+        let Some(v) = change else { return };
+        self.wake_all(&self.version);
+        if v & CHANGE_WAITING != 0 {
+            self.change_wakers.wake_all(&self.version);
+        }
+    }
+
+    ///Block until [Self::version] is no longer `last_version`, returning the new version
+    pub fn wait_for_change(&self, last_version: u32) -> u32 {
+        let mut backoff = Backoff::new(self.wait_strategy);
+        loop {
+            let v = self.version.load(Acquire);
+            if v / VERSION_STEP != last_version {
+                return v / VERSION_STEP;
+            }
+            backoff.wait(&self.version, v, None);
         }
-        self.state.store(0, Relaxed);
+    }
+
+    ///Statistics recorded since the lock was created or [Self::reset_stats] was called
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> LockStats {
+        self.stats.snapshot()
+    }
+
+    ///Zero the statistics, e.g. to measure a single run of a benchmark
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+    }
+
+    ///Async iterator yielding each new [Self::version], starting after the current one
+    pub fn changes(&self) -> Changes<'_> {
+        Changes::new(self)
+    }
+}
+
+impl Default for LockState {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-pub struct MrwLock<T: Sized> {
+#[cfg(any(feature = "deadlock_detection", debug_assertions))]
+impl Drop for LockState {
+    fn drop(&mut self) {
+        #[cfg(feature = "deadlock_detection")]
+        deadlock::forget(self);
+        #[cfg(debug_assertions)]
+        hierarchy::forget(self);
+    }
+}
+
+/// Read write lock over `T`, which can be unsized. Locks of sized data coerce to unsized ones behind a pointer
+/// the same way the data would, and [Box]ed slice and [str] locks can be made from a [Vec] or [String]
+/// ```
+/// use manual_rwlock::MrwLock;
+/// use std::{fmt::Display, sync::Arc};
+/// let shown: Arc<MrwLock<dyn Display + Send + Sync>> = Arc::new(MrwLock::new(10));
+/// assert_eq!(shown.read().unwrap().to_string(), "10");
+/// let array = MrwLock::new([1, 2, 3]);
+/// let slice: &MrwLock<[i32]> = &array;
+/// slice.write().unwrap()[0] = 4;
+/// let name: Box<MrwLock<str>> = String::from("lock").into();
+/// name.write().unwrap().make_ascii_uppercase();
+/// assert_eq!(&*name.read().unwrap(), "LOCK");
+/// ```
+// repr(C) so the layout of unsized locks allocated by hand is known
+#[repr(C)]
+pub struct MrwLock<T: ?Sized> {
     state: LockState,
     data: UnsafeCell<T>,
 }
 
+/// How a guard finds its data again when reobtained after [ReadGuard::release], for data which may have moved while released.
+/// Guards pointing to the data of the lock itself have none, as it can not move while they borrow the lock
+pub(crate) struct Source<T: ?Sized> {
+    lock: *const (),
+    find: unsafe fn(*const ()) -> *mut T,
+    find_mut: unsafe fn(*const ()) -> *mut T,
+}
+
+impl<T: ?Sized> Source<T> {
+    /// Find the data through a shared borrow, as other readers may be borrowing it too. Must not be written through
+    /// # Safety
+    /// the lock must be held
+    pub(crate) unsafe fn find(self) -> *mut T {
+        (self.find)(self.lock)
+    }
+
+    /// Find the data through a mutable borrow
+    /// # Safety
+    /// the write lock must be held
+    pub(crate) unsafe fn find_mut(self) -> *mut T {
+        (self.find_mut)(self.lock)
+    }
+}
+
+impl<U> Source<[U]> {
+    /// Borrow the slice from the data of `lock` again, as a writer could have reallocated it
+    fn slice<T: BorrowMut<[U]>>(lock: &MrwLock<T>) -> Source<[U]> {
+        unsafe fn find<T: BorrowMut<[U]>, U>(lock: *const ()) -> *mut [U] {
+            let lock = &*lock.cast::<MrwLock<T>>();
+            <T as Borrow<[U]>>::borrow(&*lock.data.get()) as *const [U] as *mut [U]
+        }
+        unsafe fn find_mut<T: BorrowMut<[U]>, U>(lock: *const ()) -> *mut [U] {
+            let lock = &*lock.cast::<MrwLock<T>>();
+            <T as BorrowMut<[U]>>::borrow_mut(&mut *lock.data.get()) as *mut [U]
+        }
+        Source {
+            lock: lock as *const MrwLock<T> as *const (),
+            find: find::<T, U>,
+            find_mut: find_mut::<T, U>,
+        }
+    }
+}
+
+impl<T: ?Sized> Clone for Source<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: ?Sized> Copy for Source<T> {}
+
+impl<T> From<Vec<T>> for Box<MrwLock<[T]>> {
+    /// Move the elements into a new lock holding exactly `vec.len()` of them
+    fn from(mut vec: Vec<T>) -> Self {
+        let len = vec.len();
+        let (layout, offset) = Layout::new::<LockState>()
+            .extend(Layout::array::<T>(len).expect("capacity overflow"))
+            .expect("capacity overflow");
+        let layout = layout.pad_to_align();
+        unsafe {
+            // LockState is not zero sized so neither is the layout
+            let mem = alloc::alloc(layout);
+            if mem.is_null() {
+                alloc::handle_alloc_error(layout);
+            }
+            mem.cast::<LockState>().write(LockState::new());
+            ptr::copy_nonoverlapping(vec.as_ptr(), mem.add(offset).cast::<T>(), len);
+            // The elements were moved, only free the buffer
+            vec.set_len(0);
+            Box::from_raw(ptr::slice_from_raw_parts_mut(mem.cast::<T>(), len) as *mut MrwLock<[T]>)
+        }
+    }
+}
+
+impl From<String> for Box<MrwLock<str>> {
+    fn from(string: String) -> Self {
+        let bytes: Box<MrwLock<[u8]>> = string.into_bytes().into();
+        // str has the same layout as [u8] and the bytes are valid UTF-8
+        unsafe { Box::from_raw(Box::into_raw(bytes) as *mut MrwLock<str>) }
+    }
+}
+
+/// Configures an [MrwLock] or [LockState] before creating it
+/// ```
+/// use manual_rwlock::{MrwLock, Policy, WaitStrategy};
+/// let mrw_lock = MrwLock::builder()
+///     .policy(Policy::WriterPreferred)
+///     .wait_strategy(WaitStrategy::Spin)
+///     .build(10);
+/// assert_eq!(*mrw_lock.read().unwrap(), 10);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct MrwLockBuilder {
+    policy: Policy,
+    wait_strategy: WaitStrategy,
+    level: Option<u32>,
+}
+
+impl MrwLockBuilder {
+    pub const fn new() -> MrwLockBuilder {
+        MrwLockBuilder {
+            policy: Policy::ReaderPreferred,
+            wait_strategy: WaitStrategy::DEFAULT,
+            level: None,
+        }
+    }
+
+    /// See [Policy], defaults to [Policy::ReaderPreferred]
+    pub const fn policy(self, policy: Policy) -> MrwLockBuilder {
+        MrwLockBuilder { policy, ..self }
+    }
+
+    /// See [WaitStrategy], defaults to [WaitStrategy::DEFAULT]
+    pub const fn wait_strategy(self, wait_strategy: WaitStrategy) -> MrwLockBuilder {
+        MrwLockBuilder {
+            wait_strategy,
+            ..self
+        }
+    }
+
+    /// See [MrwLock::with_level], by default locks have no level and are not checked
+    pub const fn level(self, level: u32) -> MrwLockBuilder {
+        MrwLockBuilder {
+            level: Some(level),
+            ..self
+        }
+    }
+
+    loom_const_fn! {
+        pub fn build<T>(self, data: T) -> MrwLock<T> {
+            MrwLock {
+                state: self.build_state(),
+                data: UnsafeCell::new(data),
+            }
+        }
+    }
+
+    loom_const_fn! {
+        pub fn build_state(self) -> LockState {
+            LockState::from_builder(self)
+        }
+    }
+}
+
+impl Default for MrwLockBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MrwLock<()> {
+    /// Start configuring a lock, the data type is given when calling [MrwLockBuilder::build]
+    pub const fn builder() -> MrwLockBuilder {
+        MrwLockBuilder::new()
+    }
+}
+
 impl<T> MrwLock<T> {
-    pub const fn new(data: T) -> MrwLock<T> {
-        MrwLock {
-            state: LockState::new(),
-            data: UnsafeCell::new(data),
+    loom_const_fn! {
+        pub fn new(data: T) -> MrwLock<T> {
+            MrwLock::with_policy(data, Policy::ReaderPreferred)
+        }
+    }
+
+    loom_const_fn! {
+        /// Create a lock which resolves contention between readers and writers using `policy`
+        /// ```
+        /// use manual_rwlock::{MrwLock, Policy};
+        /// let mrw_lock = MrwLock::with_policy(10, Policy::WriterPreferred);
+        /// let read = mrw_lock.read().unwrap();
+        /// assert_eq!(*read, 10)
+        /// ```
+        pub fn with_policy(data: T, policy: Policy) -> MrwLock<T> {
+            MrwLockBuilder::new().policy(policy).build(data)
+        }
+    }
+
+    loom_const_fn! {
+        /// Create a lock at `level` in a lock hierarchy. In debug builds a thread locking it while holding a lock at the same or a
+        /// higher level panics, so locks are always taken in increasing order of level and can not deadlock each other.
+        /// Guards released early no longer count as held, and reobtaining them is checked like any other lock.
+        /// `try_` variants can not deadlock so are not checked, neither are clones and upgrades of a held lock.
+        /// A guard moved to another thread still counts as held by the thread that obtained it until it is dropped,
+        /// so move an [ArcReadGuard] or [ArcWriteGuard] instead, which never count as held by any thread.
+        /// Locks obtained directly on the [LockState] are checked but not counted as held either
+        /// ```
+        /// use manual_rwlock::MrwLock;
+        /// let accounts = MrwLock::with_level(vec![10, 20], 1);
+        /// let log = MrwLock::with_level(Vec::new(), 2);
+        /// let mut balances = accounts.write().unwrap();
+        /// balances.swap(0, 1);
+        /// // Locking `accounts` while holding `log` would panic
+        /// log.write().unwrap().push("swapped");
+        /// ```
+        pub fn with_level(data: T, level: u32) -> MrwLock<T> {
+            MrwLockBuilder::new().level(level).build(data)
+        }
+    }
+}
+
+impl<T: ?Sized> MrwLock<T> {
+    /// Whether a thread panicked while holding a write lock, or it was poisoned with [WriteGuard::poison], see [PoisonError]
+    pub fn is_poisoned(&self) -> bool {
+        self.state.is_poisoned()
+    }
+
+    /// Why the lock was poisoned, either the reason given to [WriteGuard::poison] or the panic.
+    /// The panic message is only recorded once [install_poison_hook] is called, as the library does not set a panic hook itself
+    /// ```
+    /// use manual_rwlock::MrwLock;
+    /// let mrw_lock = MrwLock::new(10);
+    /// assert_eq!(mrw_lock.poison_reason(), None);
+    /// let write = mrw_lock.write().unwrap();
+    /// write.poison("checksum mismatch");
+    /// drop(write);
+    /// assert_eq!(mrw_lock.poison_reason().as_deref(), Some("checksum mismatch"));
+    /// let Err(err) = mrw_lock.read() else { panic!() };
+    /// assert_eq!(err.to_string(), "poisoned lock: checksum mismatch");
+    /// ```
+    pub fn poison_reason(&self) -> Option<Arc<str>> {
+        self.state.poison_reason()
+    }
+
+    /// Number of times the data was modified under a write lock and the lock released, wrapping around.
+    /// Only mutable access through a [WriteGuard] or [SliceWriteGuard] counts as modifying
+    pub fn version(&self) -> u32 {
+        self.state.version()
+    }
+
+    /// Block until the data has been modified since `last_version` was read with [Self::version], returning the new version
+    /// ```
+    /// use manual_rwlock::MrwLock;
+    /// let mrw_lock = MrwLock::new(10);
+    /// let version = mrw_lock.version();
+    /// std::thread::scope(|s| {
+    ///     s.spawn(|| *mrw_lock.write().unwrap() = 5);
+    ///     assert_eq!(mrw_lock.wait_for_change(version), version + 1);
+    /// });
+    /// assert_eq!(*mrw_lock.read().unwrap(), 5);
+    /// ```
+    pub fn wait_for_change(&self, last_version: u32) -> u32 {
+        self.state.wait_for_change(last_version)
+    }
+
+    /// Contention statistics to find hot locks, recorded with the `stats` feature.
+    /// Counts are since the lock was created or [Self::reset_stats] was called
+    /// ```
+    /// use manual_rwlock::MrwLock;
+    /// let mrw_lock = MrwLock::new(0);
+    /// let read = mrw_lock.read().unwrap();
+    /// assert!(mrw_lock.try_write().is_err());
+    /// drop(read);
+    /// *mrw_lock.write().unwrap() += 1;
+    /// let stats = mrw_lock.stats();
+    /// assert_eq!((stats.reads, stats.writes, stats.contended), (1, 1, 1));
+    /// mrw_lock.reset_stats();
+    /// assert_eq!(mrw_lock.stats().writes, 0);
+    /// ```
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> LockStats {
+        self.state.stats()
+    }
+
+    /// Zero the statistics, see [Self::stats]
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&self) {
+        self.state.reset_stats();
+    }
+
+    /// Async iterator over changes to the data, see [Changes]
+    /// ```
+    /// use manual_rwlock::MrwLock;
+    /// async fn watch(config: &MrwLock<String>) {
+    ///     let mut changes = config.changes();
+    ///     while let Some(_version) = changes.next().await {
+    ///         println!("config is now {}", *config.read_async().await.unwrap());
+    ///     }
+    /// }
+    /// ```
+    pub fn changes(&self) -> Changes<'_> {
+        self.state.changes()
+    }
+
+    /// Clear the poisoned flag so later locks succeed, for use once the data has been recovered
+    /// ```
+    /// use manual_rwlock::{LockError, MrwLock};
+    /// let mrw_lock = MrwLock::new(10);
+    /// std::thread::scope(|s| {
+    ///     let panicked = s.spawn(|| {
+    ///         let _write = mrw_lock.write().unwrap();
+    ///         panic!();
+    ///     });
+    ///     assert!(panicked.join().is_err());
+    /// });
+    /// let Err(LockError::Poisoned(err)) = mrw_lock.write() else { panic!() };
+    /// let mut write = err.into_inner();
+    /// *write = 0;
+    /// mrw_lock.clear_poison();
+    /// drop(write);
+    /// assert!(mrw_lock.read().is_ok())
+    /// ```
+    pub fn clear_poison(&self) {
+        self.state.clear_poison();
+    }
+
+    /// Read a copy of the data without taking a read lock, like a seqlock. Readers do not write to the lock state,
+    /// so many threads can read a small hot value without contending on it.
+    /// `f` is given a copy made while no writer was modifying the data. If a writer gets in the way a few times a read lock is taken instead.
+    /// Poisoning is not reported, see [SeqCell] for a cell with no lock at all
+    /// ```
+    /// use manual_rwlock::MrwLock;
+    /// let range = MrwLock::new((0, 10));
+    /// std::thread::scope(|s| {
+    ///     s.spawn(|| *range.write().unwrap() = (5, 15));
+    ///     assert_eq!(range.optimistic_read(|(start, end)| end - start), 10);
+    /// });
+    /// ```
+    pub fn optimistic_read<R>(&self, f: impl FnOnce(&T) -> R) -> R
+    where
+        T: Copy,
+    {
+        for _ in 0..OPTIMISTIC_TRIES {
+            if let Some(copy) = unsafe { self.state.seq.read(self.data.get()) } {
+                return f(&copy);
+            }
+            spin_loop();
         }
+        let copy = match self.read() {
+            Ok(guard) => *guard,
+            Err(LockError::Poisoned(err)) => *err.into_inner(),
+            Err(e) => panic!("could not read lock: {e}"),
+        };
+        f(&copy)
+    }
+
+    pub fn try_read(&self) -> LockResult<ReadGuard<'_, T>> {
+        let res = self.state.try_read();
+        with_guard(res, || ReadGuard::new(&self.state, self.data.get(), None))
+    }
+
+    pub fn read(&self) -> LockResult<ReadGuard<'_, T>> {
+        let res = self.state.read();
+        with_guard(res, || ReadGuard::new(&self.state, self.data.get(), None))
+    }
+
+    /// Same as [Self::read] but gives up with [LockError::TimedOut] after `timeout`
+    pub fn read_for(&self, timeout: Duration) -> LockResult<ReadGuard<'_, T>> {
+        let res = self.state.read_for(timeout);
+        with_guard(res, || ReadGuard::new(&self.state, self.data.get(), None))
+    }
+
+    /// Same as [Self::read] but gives up with [LockError::TimedOut] once `deadline` has passed
+    pub fn read_until(&self, deadline: Instant) -> LockResult<ReadGuard<'_, T>> {
+        let res = self.state.read_until(deadline);
+        with_guard(res, || ReadGuard::new(&self.state, self.data.get(), None))
+    }
+
+    pub fn try_upgradable_read(&self) -> LockResult<UpgradableReadGuard<'_, T>> {
+        let res = self.state.try_upgradable_read();
+        with_guard(res, || {
+            UpgradableReadGuard::new(&self.state, self.data.get())
+        })
+    }
+
+    /// Obtain a read lock that can later be upgraded to a write lock without releasing it.
+    /// Only one upgradable read lock can be held at a time, but it can be held alongside normal read locks
+    pub fn upgradable_read(&self) -> LockResult<UpgradableReadGuard<'_, T>> {
+        let res = self.state.upgradable_read();
+        with_guard(res, || {
+            UpgradableReadGuard::new(&self.state, self.data.get())
+        })
     }
 
-    pub fn try_read(&self) -> LockResult<ReadGuard<T>> {
-        self.state.try_read()?;
-        Ok(ReadGuard {
-            state: &self.state,
-            data: self.data.get(),
+    /// Same as [Self::upgradable_read] but gives up with [LockError::TimedOut] after `timeout`
+    pub fn upgradable_read_for(&self, timeout: Duration) -> LockResult<UpgradableReadGuard<'_, T>> {
+        let res = self.state.upgradable_read_for(timeout);
+        with_guard(res, || {
+            UpgradableReadGuard::new(&self.state, self.data.get())
         })
     }
 
-    pub fn read(&self) -> LockResult<ReadGuard<T>> {
-        self.state.read()?;
-        Ok(ReadGuard {
-            state: &self.state,
-            data: self.data.get(),
+    /// Same as [Self::upgradable_read] but gives up with [LockError::TimedOut] once `deadline` has passed
+    pub fn upgradable_read_until(
+        &self,
+        deadline: Instant,
+    ) -> LockResult<UpgradableReadGuard<'_, T>> {
+        let res = self.state.upgradable_read_until(deadline);
+        with_guard(res, || {
+            UpgradableReadGuard::new(&self.state, self.data.get())
         })
     }
 
-    pub fn try_write(&self) -> LockResult<WriteGuard<T>> {
-        self.state.try_write()?;
-        Ok(WriteGuard {
-            state: &self.state,
-            data: self.data.get(),
+    pub fn try_write(&self) -> LockResult<WriteGuard<'_, T>> {
+        let res = self.state.try_write();
+        with_guard(res, || WriteGuard::new(&self.state, self.data.get(), None))
+    }
+
+    pub fn write(&self) -> LockResult<WriteGuard<'_, T>> {
+        let res = self.state.write();
+        with_guard(res, || WriteGuard::new(&self.state, self.data.get(), None))
+    }
+
+    /// Same as [Self::write] but gives up with [LockError::TimedOut] after `timeout`
+    pub fn write_for(&self, timeout: Duration) -> LockResult<WriteGuard<'_, T>> {
+        let res = self.state.write_for(timeout);
+        with_guard(res, || WriteGuard::new(&self.state, self.data.get(), None))
+    }
+
+    /// Same as [Self::write] but gives up with [LockError::TimedOut] once `deadline` has passed
+    pub fn write_until(&self, deadline: Instant) -> LockResult<WriteGuard<'_, T>> {
+        let res = self.state.write_until(deadline);
+        with_guard(res, || WriteGuard::new(&self.state, self.data.get(), None))
+    }
+
+    /// Same as [Self::read] but waits without blocking the thread, so it can be awaited on any executor.
+    /// Dropping the future before it completes gives up waiting
+    /// ```
+    /// use manual_rwlock::MrwLock;
+    /// async fn get(mrw_lock: &MrwLock<i32>) -> i32 {
+    ///     *mrw_lock.read_async().await.unwrap()
+    /// }
+    /// ```
+    pub async fn read_async(&self) -> LockResult<ReadGuard<'_, T>> {
+        let res = self.state.read_async().await;
+        with_guard(res, || ReadGuard::new(&self.state, self.data.get(), None))
+    }
+
+    /// Same as [Self::write] but waits without blocking the thread, see [Self::read_async]
+    pub async fn write_async(&self) -> LockResult<WriteGuard<'_, T>> {
+        let res = self.state.write_async().await;
+        with_guard(res, || WriteGuard::new(&self.state, self.data.get(), None))
+    }
+
+    /// Same as [Self::read] but the guard holds a clone of the [Arc] rather than borrowing the lock, see [ArcReadGuard]
+    pub fn read_arc(self: &Arc<Self>) -> LockResult<ArcReadGuard<T>> {
+        let res = self.state.read();
+        with_guard(res, || unsafe {
+            ArcReadGuard::from_locked(self.clone(), self.data.get(), None)
         })
     }
 
-    pub fn write(&self) -> LockResult<WriteGuard<T>> {
-        self.state.write()?;
-        Ok(WriteGuard {
-            state: &self.state,
-            data: self.data.get(),
+    /// Same as [Self::write] but the guard holds a clone of the [Arc] rather than borrowing the lock, see [ArcWriteGuard]
+    pub fn write_arc(self: &Arc<Self>) -> LockResult<ArcWriteGuard<T>> {
+        let res = self.state.write();
+        with_guard(res, || unsafe {
+            ArcWriteGuard::from_locked(self.clone(), self.data.get(), None)
         })
     }
 }
 
-impl<T> MrwLock<T>
-{
-    pub fn try_read_slice<U>(&self) -> LockResult<SliceReadGuard<U>>
+impl<T> MrwLock<T> {
+    pub fn try_read_slice<U>(&self) -> LockResult<SliceReadGuard<'_, U>>
+    where
+        T: BorrowMut<[U]>,
+    {
+        let res = self.state.try_read();
+        let source = Source::slice(self);
+        with_guard(res, || {
+            ReadGuard::new(&self.state, unsafe { source.find() }, Some(source))
+        })
+    }
+
+    pub fn read_slice<U>(&self) -> LockResult<SliceReadGuard<'_, U>>
+    where
+        T: BorrowMut<[U]>,
+    {
+        let res = self.state.read();
+        let source = Source::slice(self);
+        with_guard(res, || {
+            ReadGuard::new(&self.state, unsafe { source.find() }, Some(source))
+        })
+    }
+
+    /// Same as [Self::read_slice] but gives up with [LockError::TimedOut] after `timeout`
+    pub fn read_slice_for<U>(&self, timeout: Duration) -> LockResult<SliceReadGuard<'_, U>>
+    where
+        T: BorrowMut<[U]>,
+    {
+        let res = self.state.read_for(timeout);
+        let source = Source::slice(self);
+        with_guard(res, || {
+            ReadGuard::new(&self.state, unsafe { source.find() }, Some(source))
+        })
+    }
+
+    /// Same as [Self::read_slice] but gives up with [LockError::TimedOut] once `deadline` has passed
+    pub fn read_slice_until<U>(&self, deadline: Instant) -> LockResult<SliceReadGuard<'_, U>>
+    where
+        T: BorrowMut<[U]>,
+    {
+        let res = self.state.read_until(deadline);
+        let source = Source::slice(self);
+        with_guard(res, || {
+            ReadGuard::new(&self.state, unsafe { source.find() }, Some(source))
+        })
+    }
+
+    pub fn try_write_slice<U>(&self) -> LockResult<SliceWriteGuard<'_, U>>
+    where
+        T: BorrowMut<[U]>,
+    {
+        let res = self.state.try_write();
+        let source = Source::slice(self);
+        with_guard(res, || {
+            WriteGuard::new(&self.state, unsafe { source.find_mut() }, Some(source))
+        })
+    }
+
+    pub fn write_slice<U>(&self) -> LockResult<SliceWriteGuard<'_, U>>
+    where
+        T: BorrowMut<[U]>,
+    {
+        let res = self.state.write();
+        let source = Source::slice(self);
+        with_guard(res, || {
+            WriteGuard::new(&self.state, unsafe { source.find_mut() }, Some(source))
+        })
+    }
+
+    /// Same as [Self::write_slice] but gives up with [LockError::TimedOut] after `timeout`
+    pub fn write_slice_for<U>(&self, timeout: Duration) -> LockResult<SliceWriteGuard<'_, U>>
+    where
+        T: BorrowMut<[U]>,
+    {
+        let res = self.state.write_for(timeout);
+        let source = Source::slice(self);
+        with_guard(res, || {
+            WriteGuard::new(&self.state, unsafe { source.find_mut() }, Some(source))
+        })
+    }
+
+    /// Same as [Self::write_slice] but gives up with [LockError::TimedOut] once `deadline` has passed
+    pub fn write_slice_until<U>(&self, deadline: Instant) -> LockResult<SliceWriteGuard<'_, U>>
+    where
+        T: BorrowMut<[U]>,
+    {
+        let res = self.state.write_until(deadline);
+        let source = Source::slice(self);
+        with_guard(res, || {
+            WriteGuard::new(&self.state, unsafe { source.find_mut() }, Some(source))
+        })
+    }
+
+    /// Same as [Self::read_slice] but waits without blocking the thread, see [Self::read_async]
+    pub async fn read_slice_async<U>(&self) -> LockResult<SliceReadGuard<'_, U>>
     where
         T: BorrowMut<[U]>,
     {
-        self.state.try_read()?;
-        Ok(SliceReadGuard {
-            state: &self.state,
-            data: unsafe { (*self.data.get()).borrow_mut() } as *mut [U],
+        let res = self.state.read_async().await;
+        let source = Source::slice(self);
+        with_guard(res, || {
+            ReadGuard::new(&self.state, unsafe { source.find() }, Some(source))
         })
     }
 
-    pub fn read_slice<U>(&self) -> LockResult<SliceReadGuard<U>>
+    /// Same as [Self::write_slice] but waits without blocking the thread, see [Self::read_async]
+    pub async fn write_slice_async<U>(&self) -> LockResult<SliceWriteGuard<'_, U>>
     where
         T: BorrowMut<[U]>,
     {
-        self.state.read()?;
-        Ok(SliceReadGuard {
-            state: &self.state,
-            data: unsafe { (*self.data.get()).borrow_mut() } as *mut [U],
+        let res = self.state.write_async().await;
+        let source = Source::slice(self);
+        with_guard(res, || {
+            WriteGuard::new(&self.state, unsafe { source.find_mut() }, Some(source))
         })
     }
 
-    pub fn try_write_slice<U>(&self) -> LockResult<SliceWriteGuard<U>>
+    /// Same as [Self::read_slice] but the guard holds a clone of the [Arc] rather than borrowing the lock, see [ArcSliceReadGuard]
+    pub fn read_slice_arc<U>(self: &Arc<Self>) -> LockResult<ArcSliceReadGuard<T, U>>
     where
         T: BorrowMut<[U]>,
     {
-        self.state.try_write()?;
-        Ok(SliceWriteGuard {
-            state: &self.state,
-            data: unsafe { (*self.data.get()).borrow_mut() } as *mut [U],
+        let res = self.state.read();
+        let source = Source::slice(self);
+        with_guard(res, || unsafe {
+            ArcSliceReadGuard::from_locked(self.clone(), source.find(), Some(source))
         })
     }
 
-    pub fn write_slice<U>(&self) -> LockResult<SliceWriteGuard<U>>
+    /// Same as [Self::write_slice] but the guard holds a clone of the [Arc] rather than borrowing the lock, see [ArcSliceWriteGuard]
+    pub fn write_slice_arc<U>(self: &Arc<Self>) -> LockResult<ArcSliceWriteGuard<T, U>>
     where
         T: BorrowMut<[U]>,
     {
-        self.state.write()?;
-        Ok(SliceWriteGuard {
-            state: &self.state,
-            data: unsafe { (*self.data.get()).borrow_mut() } as *mut [U],
+        let res = self.state.write();
+        let source = Source::slice(self);
+        with_guard(res, || unsafe {
+            ArcSliceWriteGuard::from_locked(self.clone(), source.find_mut(), Some(source))
         })
     }
 }
 
+unsafe impl<T: ?Sized + Send> Send for MrwLock<T> {}
 
-unsafe impl<T> Send for MrwLock<T>{}
-unsafe impl<T> Sync for MrwLock<T>{}
+/// Shared like [std::sync::RwLock], so `T` must be [Sync] for concurrent readers and [Send] as writers can move data out
+/// ```compile_fail
+/// use manual_rwlock::MrwLock;
+/// use std::cell::Cell;
+/// let mrw_lock = MrwLock::new(Cell::new(0));
+/// std::thread::scope(|s| {
+///     s.spawn(|| mrw_lock.read().unwrap().set(1));
+/// });
+/// ```
+/// ```compile_fail
+/// use manual_rwlock::MrwLock;
+/// use std::{rc::Rc, sync::Arc};
+/// let mrw_lock = Arc::new(MrwLock::new(Rc::new(0)));
+/// std::thread::spawn(move || drop(mrw_lock.write().unwrap()));
+/// ```
+unsafe impl<T: ?Sized + Send + Sync> Sync for MrwLock<T> {}
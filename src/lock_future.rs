@@ -0,0 +1,162 @@
+//! Async acquisition of a [LockState]
+use crate::{stats::WaitStart, LockResult, LockState, Policy};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::Ordering::Relaxed,
+    task::{Context, Poll},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Mode {
+    /// Add a reader, also setting the flag which must be 0 or [UPGRADABLE](crate::UPGRADABLE)
+    Read(u32),
+    Write,
+    /// Convert a held read lock to a write lock
+    ToWrite,
+}
+
+/// Future obtaining a lock on a [LockState] without blocking the thread, returned by [LockState::read_async],
+/// [LockState::write_async] and [LockState::to_write_async].
+/// It works with any executor, waits alongside blocking callers of the same lock and follows its [Policy].
+/// Dropping it before it completes gives up its place in the queue, so it is cancel safe
+#[must_use = "futures do nothing unless polled"]
+pub struct LockFuture<'a> {
+    state: &'a LockState,
+    mode: Mode,
+    /// Ticket taken with [Policy::Fifo], `turn` is set once it is served
+    ticket: Option<u32>,
+    turn: bool,
+    /// Phase a waiting reader arrived in with [Policy::PhaseFair]
+    arrival: Option<u32>,
+    /// Whether this is counted as a waiting writer
+    registered: bool,
+    /// Whether this set [UPGRADING](crate::UPGRADING) to upgrade
+    claimed: bool,
+    /// Key of the registered waker
+    key: Option<u32>,
+    /// Set while this is counted as waiting for the lock
+    waited: Option<WaitStart>,
+    done: bool,
+}
+
+impl<'a> LockFuture<'a> {
+    pub(crate) fn new(state: &'a LockState, mode: Mode) -> LockFuture<'a> {
+        LockFuture {
+            state,
+            mode,
+            ticket: None,
+            turn: false,
+            arrival: None,
+            registered: false,
+            claimed: false,
+            key: None,
+            waited: None,
+            done: false,
+        }
+    }
+
+    /// Try to obtain the lock without waiting, `None` if it has to wait
+    fn attempt(&mut self) -> Option<LockResult<()>> {
+        let state = self.state;
+        // Upgrades already hold a read lock so do not queue
+        if state.policy == Policy::Fifo && !self.turn && !matches!(self.mode, Mode::ToWrite) {
+            let ticket = *self.ticket.get_or_insert_with(|| state.tickets.take());
+            if !state.tickets.is_serving(ticket) {
+                return None;
+            }
+            self.turn = true;
+        }
+        let mut s = state.state.load(Relaxed);
+        match self.mode {
+            Mode::Read(flag) => state.attempt_read(flag, &mut self.arrival, &mut s),
+            Mode::Write => state
+                .attempt_write(&mut self.registered, &mut s)
+                .then(|| state.check_poison()),
+            Mode::ToWrite => {
+                if !self.claimed {
+                    if let Err(e) = state.claim_upgrade() {
+                        return Some(Err(e));
+                    }
+                    self.claimed = true;
+                }
+                state.attempt_upgrade(&mut s).then(|| state.check_poison())
+            }
+        }
+    }
+
+    fn finish(&mut self, res: LockResult<()>) -> LockResult<()> {
+        self.done = true;
+        self.state.wakers.remove(&mut self.key);
+        if let Some(phase) = self.arrival.take() {
+            self.state.leave_phase(phase);
+        }
+        if self.registered {
+            self.state.unregister_writer();
+        }
+        if self.turn {
+            self.state.tickets.advance();
+            self.state.notify_async();
+        }
+        if let Some(start) = self.waited.take() {
+            self.state.stop_waiting(start);
+        }
+        res
+    }
+}
+
+impl Future for LockFuture<'_> {
+    type Output = LockResult<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        assert!(!this.done, "LockFuture polled after completion");
+        if let Some(res) = this.attempt() {
+            return Poll::Ready(this.finish(res));
+        }
+        this.state
+            .wakers
+            .register(&mut this.key, cx.waker(), &this.state.state);
+        // Releases before registering are seen by trying again, releases after it wake this task
+        match this.attempt() {
+            Some(res) => Poll::Ready(this.finish(res)),
+            None => {
+                if this.waited.is_none() {
+                    this.waited = Some(this.state.start_waiting());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for LockFuture<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let state = self.state;
+        state.wakers.remove(&mut self.key);
+        if let Some(phase) = self.arrival.take() {
+            state.leave_phase(phase);
+        }
+        if self.registered {
+            state.unregister_writer();
+            state.writer_gave_up();
+        }
+        if self.claimed {
+            state.abandon_upgrade();
+        }
+        if self.turn {
+            state.tickets.advance();
+        } else if let Some(ticket) = self.ticket {
+            state.tickets.abandon(ticket);
+        }
+        if self.ticket.is_some() {
+            state.notify_async();
+        }
+        if let Some(start) = self.waited.take() {
+            state.stop_waiting(start);
+        }
+    }
+}
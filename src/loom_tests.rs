@@ -0,0 +1,151 @@
+//! Model checked tests, run with `cargo test --release --features loom`. The doc examples are skipped under the feature.
+//! The data is a loom [UnsafeCell] so loom reports any access not ordered by the lock
+use crate::{LockError, MrwLock, Policy, SeqCell, WaitStrategy};
+use loom::{cell::UnsafeCell, sync::Arc, thread};
+
+type Lock = Arc<MrwLock<UnsafeCell<u32>>>;
+
+/// Parking only yields under loom, spinning first would multiply the interleavings to explore
+fn lock(policy: Policy) -> Lock {
+    Arc::new(
+        MrwLock::builder()
+            .policy(policy)
+            .wait_strategy(WaitStrategy::Park)
+            .build(UnsafeCell::new(0)),
+    )
+}
+
+fn model(f: impl Fn() + Sync + Send + 'static) {
+    let mut builder = loom::model::Builder::new();
+    builder.preemption_bound.get_or_insert(3);
+    builder.check(f);
+}
+
+fn get(cell: &UnsafeCell<u32>) -> u32 {
+    cell.with(|v| unsafe { *v })
+}
+
+fn increment(cell: &UnsafeCell<u32>) {
+    cell.with_mut(|v| unsafe { *v += 1 });
+}
+
+#[test]
+fn read_write_exclusion() {
+    for policy in [Policy::ReaderPreferred, Policy::WriterPreferred] {
+        model(move || {
+            let lock = lock(policy);
+            let writer = {
+                let lock = lock.clone();
+                thread::spawn(move || increment(&lock.write().unwrap()))
+            };
+            let value = get(&lock.read().unwrap());
+            assert!(value <= 1);
+            increment(&lock.write().unwrap());
+            writer.join().unwrap();
+            assert_eq!(get(&lock.read().unwrap()), 2);
+        });
+    }
+}
+
+#[test]
+fn conversions() {
+    model(|| {
+        let lock = lock(Policy::ReaderPreferred);
+        let writer = {
+            let lock = lock.clone();
+            thread::spawn(move || increment(&lock.write().unwrap()))
+        };
+        let read = lock.read().unwrap();
+        let before = get(&read);
+        let write = read.to_write().unwrap();
+        increment(&write);
+        let read = write.to_read();
+        assert_eq!(get(&read), before + 1);
+        drop(read);
+        writer.join().unwrap();
+        assert_eq!(get(&lock.read().unwrap()), 2);
+    });
+}
+
+#[test]
+fn upgradable_conversions() {
+    model(|| {
+        let lock = lock(Policy::ReaderPreferred);
+        let reader = {
+            let lock = lock.clone();
+            thread::spawn(move || get(&lock.read().unwrap()))
+        };
+        let upgradable = lock.upgradable_read().unwrap();
+        let write = upgradable.upgrade();
+        increment(&write);
+        let read = write.to_read();
+        assert_eq!(get(&read), 1);
+        drop(read);
+        assert!(reader.join().unwrap() <= 1);
+    });
+}
+
+#[test]
+fn early_release_reobtain() {
+    model(|| {
+        let lock = lock(Policy::ReaderPreferred);
+        let writer = {
+            let lock = lock.clone();
+            thread::spawn(move || increment(&lock.write().unwrap()))
+        };
+        let read = lock.read().unwrap();
+        let before = get(&read);
+        unsafe { read.early_release() };
+        unsafe { read.reobtain().unwrap() };
+        assert!(get(&read) >= before);
+        drop(read);
+        let write = lock.write().unwrap();
+        unsafe { write.early_release() };
+        unsafe { write.reobtain().unwrap() };
+        increment(&write);
+        drop(write);
+        writer.join().unwrap();
+        assert_eq!(get(&lock.read().unwrap()), 2);
+    });
+}
+
+#[test]
+fn poison_visible_to_next_lock() {
+    model(|| {
+        let lock = lock(Policy::ReaderPreferred);
+        let writer = {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                let write = lock.write().unwrap();
+                increment(&write);
+                write.poison("model");
+            })
+        };
+        match lock.read() {
+            Ok(read) => assert_eq!(get(&read), 0),
+            Err(LockError::Poisoned(err)) => {
+                assert_eq!(err.reason(), Some("model"));
+                assert_eq!(get(&err.into_inner()), 1);
+            }
+            Err(e) => panic!("{e}"),
+        }
+        writer.join().unwrap();
+        assert!(lock.is_poisoned());
+    });
+}
+
+#[test]
+fn seq_cell_updates() {
+    model(|| {
+        let cell = Arc::new(SeqCell::new((0, 0)));
+        let writer = {
+            let cell = cell.clone();
+            thread::spawn(move || cell.update(|(a, b)| (a + 1, b + 1)))
+        };
+        cell.update(|(a, b)| (a + 1, b + 1));
+        let (a, b) = cell.get();
+        assert_eq!(a, b);
+        writer.join().unwrap();
+        assert_eq!(cell.get(), (2, 2));
+    });
+}
@@ -0,0 +1,149 @@
+use crate::{hierarchy::Held, reobtained::ReleasedAt, LockResult, LockState, Reobtained};
+use std::{
+    ops::Deref,
+    time::{Duration, Instant},
+};
+
+/// Read guard pointing to part of the locked data, returned by [ReadGuard::map](crate::ReadGuard::map),
+/// [SliceReadGuard::slice](crate::SliceReadGuard::slice) and the other projections.
+/// Still holds the whole read lock. It can not be converted to a write lock, as the projection was made through a shared reference
+/// ```
+/// use manual_rwlock::MrwLock;
+/// struct Config { name: String, retries: u32 }
+/// let mrw_lock = MrwLock::new(Config { name: "server".into(), retries: 3 });
+/// let name = mrw_lock.read().unwrap().map(|config| config.name.as_str());
+/// assert_eq!(&*name, "server");
+/// ```
+pub struct MappedReadGuard<'a, T: ?Sized> {
+    pub(super) state: &'a LockState,
+    pub(super) data: *const T,
+    pub(super) released_at: ReleasedAt,
+    pub(super) held: Held,
+}
+
+impl<'a, T: ?Sized> MappedReadGuard<'a, T> {
+    /// Wrap a read lock already held on `state`, it is released when the guard is dropped
+    pub(crate) fn new(state: &'a LockState, data: *const T) -> Self {
+        MappedReadGuard::converted(state, data, Held::new(state))
+    }
+
+    /// Same as [Self::new] for a lock taken over from a converted guard, along with its record of holding it
+    pub(crate) fn converted(state: &'a LockState, data: *const T, held: Held) -> Self {
+        MappedReadGuard {
+            state,
+            data,
+            released_at: ReleasedAt::new(),
+            held,
+        }
+    }
+
+    /// Project further into the data
+    pub fn map<U: ?Sized>(self, f: impl FnOnce(&T) -> &U) -> MappedReadGuard<'a, U> {
+        let data = f(unsafe { &*self.data }) as *const U;
+        let (state, held) = (self.state, self.held.take());
+        std::mem::forget(self);
+        MappedReadGuard::converted(state, data, held)
+    }
+
+    /// Project further into the data if `f` returns `Some`, otherwise hand this guard back
+    pub fn try_map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> Option<&U>,
+    ) -> Result<MappedReadGuard<'a, U>, Self> {
+        let Some(data) = f(unsafe { &*self.data }).map(|data| data as *const U) else {
+            return Err(self);
+        };
+        let (state, held) = (self.state, self.held.take());
+        std::mem::forget(self);
+        Ok(MappedReadGuard::converted(state, data, held))
+    }
+
+    /// Releases lock without dropping object, see [ReadGuard::early_release](crate::ReadGuard::early_release)
+    /// # Safety
+    /// Do not access contents before reobtaining lock with either reobtain or try_reobtain.
+    /// Writers must not move or free the part of the data this points to while released,
+    /// e.g. a field stored inline in the lock is fine but an element of a `Vec` that may be reallocated is not
+    pub unsafe fn early_release(&self) {
+        self.held.release();
+        self.released_at.release_read(self.state);
+        self.state.drop_read();
+    }
+
+    /// block until lock can be reobtained, returning whether the data was modified while released.
+    /// If the lock is poisoned it is still reobtained and [LockError::Poisoned](crate::LockError::Poisoned) is returned
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain(&self) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.read())
+    }
+
+    /// Same as [Self::reobtain] but gives up with [LockError::TimedOut](crate::LockError::TimedOut) after `timeout`
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain_for(&self, timeout: Duration) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.read_for(timeout))
+    }
+
+    /// Same as [Self::reobtain] but gives up with [LockError::TimedOut](crate::LockError::TimedOut) once `deadline` has passed
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain_until(&self, deadline: Instant) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.read_until(deadline))
+    }
+
+    /// Same as [Self::reobtain] but waits without blocking the thread.
+    /// If the future is dropped before it completes the lock is not reobtained
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub async unsafe fn reobtain_async(&self) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.read_async().await)
+    }
+
+    /// attempt to reobtain lock, if not possible at this time return `LockError:WouldBlock`
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn try_reobtain(&self) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.try_read())
+    }
+}
+
+impl<T: ?Sized> Drop for MappedReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.state.drop_read();
+    }
+}
+
+impl<T: ?Sized> Deref for MappedReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data }
+    }
+}
+
+impl<T: ?Sized> Clone for MappedReadGuard<'_, T> {
+    fn clone(&self) -> Self {
+        self.state.clone_read().unwrap();
+        MappedReadGuard::new(self.state, self.data)
+    }
+}
+
+/// Unlike a [ReadGuard](crate::ReadGuard) a mapped guard can never become a write guard, so it only hands out `&T`
+/// wherever it is sent and `T: Sync` is enough. Other parts of the locked data are out of its reach
+/// ```compile_fail
+/// use manual_rwlock::MrwLock;
+/// use std::cell::Cell;
+/// let mrw_lock = MrwLock::new((Cell::new(0), 0));
+/// let cell = mrw_lock.read().unwrap().map(|(cell, _)| cell);
+/// std::thread::scope(|s| {
+///     s.spawn(move || cell.set(1));
+///     mrw_lock.read().unwrap().0.set(2);
+/// });
+/// ```
+unsafe impl<T: ?Sized + Sync> Send for MappedReadGuard<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for MappedReadGuard<'_, T> {}
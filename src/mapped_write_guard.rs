@@ -0,0 +1,156 @@
+use crate::{
+    hierarchy::Held, reobtained::ReleasedAt, LockResult, LockState, MappedReadGuard, Reobtained,
+};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Write guard pointing to part of the locked data, returned by [WriteGuard::map](crate::WriteGuard::map),
+/// [SliceWriteGuard::slice](crate::SliceWriteGuard::slice) and the other projections. Still holds the whole write lock
+/// ```
+/// use manual_rwlock::MrwLock;
+/// let mrw_lock = MrwLock::new((String::from("count"), 0));
+/// let mut count = mrw_lock.write().unwrap().map(|(_, count)| count);
+/// *count += 1;
+/// let count = count.to_read();
+/// assert_eq!(*count, 1);
+/// ```
+pub struct MappedWriteGuard<'a, T: ?Sized> {
+    pub(super) state: &'a LockState,
+    pub(super) data: *mut T,
+    pub(super) released_at: ReleasedAt,
+    pub(super) held: Held,
+}
+
+impl<'a, T: ?Sized> MappedWriteGuard<'a, T> {
+    /// Wrap a write lock taken over from a converted guard along with its `held` record, it is released when the guard is dropped
+    pub(crate) fn converted(state: &'a LockState, data: *mut T, held: Held) -> Self {
+        MappedWriteGuard {
+            state,
+            data,
+            released_at: ReleasedAt::new(),
+            held,
+        }
+    }
+
+    /// Project further into the data
+    pub fn map<U: ?Sized>(self, f: impl FnOnce(&mut T) -> &mut U) -> MappedWriteGuard<'a, U> {
+        self.state.mark_modified();
+        let data = f(unsafe { &mut *self.data }) as *mut U;
+        let (state, held) = (self.state, self.held.take());
+        std::mem::forget(self);
+        MappedWriteGuard::converted(state, data, held)
+    }
+
+    /// Project further into the data if `f` returns `Some`, otherwise hand this guard back.
+    /// Only a successful projection counts as modifying the data, so `f` should not modify it before returning `None`
+    pub fn try_map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&mut T) -> Option<&mut U>,
+    ) -> Result<MappedWriteGuard<'a, U>, Self> {
+        // Optimistic readers still have to treat `f` as a write
+        let marked = self.state.try_mark_modified();
+        let Some(data) = f(unsafe { &mut *self.data }).map(|data| data as *mut U) else {
+            if marked {
+                self.state.unmark_modified();
+            }
+            return Err(self);
+        };
+        let (state, held) = (self.state, self.held.take());
+        std::mem::forget(self);
+        Ok(MappedWriteGuard::converted(state, data, held))
+    }
+
+    /// Convert to a read guard of the same part of the data
+    pub fn to_read(self) -> MappedReadGuard<'a, T> {
+        self.state.to_read();
+        let read = MappedReadGuard::converted(self.state, self.data, self.held.take());
+        std::mem::forget(self);
+        read
+    }
+
+    /// Poison the lock, see [WriteGuard::poison](crate::WriteGuard::poison)
+    pub fn poison(&self, reason: impl Into<Arc<str>>) {
+        self.state.poison(reason);
+    }
+
+    /// Releases lock without dropping object, see [WriteGuard::early_release](crate::WriteGuard::early_release)
+    /// # Safety
+    /// Do not access contents before reobtaining lock with either reobtain or try_reobtain.
+    /// Other writers must not move or free the part of the data this points to while released,
+    /// e.g. a field stored inline in the lock is fine but an element of a `Vec` that may be reallocated is not
+    pub unsafe fn early_release(&self) {
+        self.held.release();
+        self.released_at.release_write(self.state);
+        self.state.drop_write();
+    }
+
+    /// block until lock can be reobtained, returning whether the data was modified while released.
+    /// If the lock is poisoned it is still reobtained and [LockError::Poisoned](crate::LockError::Poisoned) is returned
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain(&self) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.write())
+    }
+
+    /// Same as [Self::reobtain] but gives up with [LockError::TimedOut](crate::LockError::TimedOut) after `timeout`
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain_for(&self, timeout: Duration) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.write_for(timeout))
+    }
+
+    /// Same as [Self::reobtain] but gives up with [LockError::TimedOut](crate::LockError::TimedOut) once `deadline` has passed
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain_until(&self, deadline: Instant) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.write_until(deadline))
+    }
+
+    /// Same as [Self::reobtain] but waits without blocking the thread.
+    /// If the future is dropped before it completes the lock is not reobtained
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub async unsafe fn reobtain_async(&self) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.write_async().await)
+    }
+
+    /// attempt to reobtain lock, if not possible at this time return `LockError:WouldBlock`
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn try_reobtain(&self) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.try_write())
+    }
+}
+
+impl<T: ?Sized> Drop for MappedWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.state.drop_write();
+    }
+}
+
+impl<T: ?Sized> Deref for MappedWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MappedWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.state.mark_modified();
+        unsafe { &mut *self.data }
+    }
+}
+
+/// Needs `T: Sync` to be sent for the same reason as [WriteGuard](crate::WriteGuard)
+unsafe impl<T: ?Sized + Send + Sync> Send for MappedWriteGuard<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for MappedWriteGuard<'_, T> {}
@@ -0,0 +1,63 @@
+//! Records panic messages so a lock poisoned by a panic can report why, once [install_poison_hook] is called
+use std::{
+    cell::RefCell,
+    panic::{self, PanicHookInfo},
+    sync::{Arc, Once},
+};
+
+thread_local! {
+    static PANIC_MESSAGE: RefCell<Option<Arc<str>>> = const { RefCell::new(None) };
+}
+
+/// Reason given to locks poisoned by a panic whose message was not recorded
+const PANICKED: &str = "panicked while holding a write lock";
+
+/// Chain a panic hook recording the message of each panic, so a lock poisoned by a panic reports it as the
+/// [poison reason](crate::MrwLock::poison_reason). Without it the reason is only that the thread panicked.
+/// The previous hook still runs, but a hook set with [std::panic::set_hook] afterwards stops messages being recorded.
+/// Installing more than once does nothing
+/// ```
+/// use manual_rwlock::MrwLock;
+/// manual_rwlock::install_poison_hook();
+/// let mrw_lock = MrwLock::new(10);
+/// std::thread::scope(|s| {
+///     s.spawn(|| {
+///         let _write = mrw_lock.write().unwrap();
+///         panic!("invalid state");
+///     })
+///     .join()
+///     .unwrap_err();
+/// });
+/// assert_eq!(mrw_lock.poison_reason().as_deref(), Some("invalid state"));
+/// ```
+pub fn install_poison_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let message = message(info);
+            let _ = PANIC_MESSAGE.try_with(|m| *m.borrow_mut() = Some(message));
+            previous(info)
+        }));
+    });
+}
+
+fn message(info: &PanicHookInfo<'_>) -> Arc<str> {
+    let payload = info.payload();
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        Arc::from(*message)
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        Arc::from(message.as_str())
+    } else {
+        Arc::from("Box<dyn Any>")
+    }
+}
+
+/// Message of the panic this thread is unwinding from if it was recorded, otherwise that it panicked
+pub(crate) fn panic_message() -> Arc<str> {
+    PANIC_MESSAGE
+        .try_with(|m| m.borrow().clone())
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| Arc::from(PANICKED))
+}
@@ -0,0 +1,187 @@
+use crate::{
+    futex::wake_all,
+    sync::{loom_const_fn, AtomicU32, Mutex, MutexGuard},
+    wait_strategy::Backoff,
+};
+use std::{
+    sync::atomic::Ordering::{Acquire, Relaxed, Release},
+    time::Instant,
+};
+
+/// Which waiting threads [LockState](crate::LockState) lets in first when readers and writers contend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Policy {
+    /// New readers may join existing readers even while a writer is waiting. Writers can be starved by a steady stream of readers
+    #[default]
+    ReaderPreferred,
+    /// New readers queue behind a waiting writer. Readers can be starved by a steady stream of writers
+    WriterPreferred,
+    /// New readers queue behind a waiting writer, but readers that arrived while a writer held or waited for the lock
+    /// are let in before the next writer, so read and write phases alternate
+    PhaseFair,
+    /// Every new lock is granted strictly in the order it was requested using tickets.
+    /// Cloning a read guard and converting a read lock to a write lock do not take a ticket
+    Fifo,
+}
+
+/// Ticket turnstile used by [Policy::Fifo]
+pub(crate) struct Tickets {
+    next: AtomicU32,
+    serving: AtomicU32,
+    /// Tickets given up before being served, skipped when reached. Only changes to `serving` while locked
+    abandoned: Mutex<Vec<u32>>,
+}
+
+impl Tickets {
+    loom_const_fn! {
+        pub(crate) fn new() -> Tickets {
+            Tickets {
+                next: AtomicU32::new(0),
+                serving: AtomicU32::new(0),
+                abandoned: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    fn abandoned(&self) -> MutexGuard<'_, Vec<u32>> {
+        self.abandoned.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Take a ticket and block until it is served. Returns false if `deadline` passed first
+    pub(crate) fn wait_turn(&self, deadline: Option<Instant>, backoff: &mut Backoff) -> bool {
+        let ticket = self.take();
+        loop {
+            let serving = self.serving.load(Acquire);
+            if serving == ticket {
+                return true;
+            }
+            if !backoff.wait(&self.serving, serving, deadline) {
+                self.abandon(ticket);
+                return false;
+            }
+        }
+    }
+
+    /// Take a ticket without waiting for it to be served, see [Self::is_serving]
+    pub(crate) fn take(&self) -> u32 {
+        self.next.fetch_add(1, Relaxed)
+    }
+
+    pub(crate) fn is_serving(&self, ticket: u32) -> bool {
+        self.serving.load(Acquire) == ticket
+    }
+
+    /// Give up a ticket that has not been served, serving the next one if it was just reached
+    pub(crate) fn abandon(&self, ticket: u32) {
+        let mut abandoned = self.abandoned();
+        if self.serving.load(Relaxed) == ticket {
+            self.advance_locked(&mut abandoned);
+        } else {
+            abandoned.push(ticket);
+        }
+    }
+
+    /// Number of tickets waiting to be served
+    #[cfg(all(test, not(feature = "loom")))]
+    pub(crate) fn queued(&self) -> u32 {
+        self.next
+            .load(Relaxed)
+            .wrapping_sub(self.serving.load(Relaxed))
+    }
+
+    /// Take a ticket only if it would be served immediately
+    pub(crate) fn try_turn(&self) -> bool {
+        let serving = self.serving.load(Acquire);
+        self.next
+            .compare_exchange(serving, serving.wrapping_add(1), Relaxed, Relaxed)
+            .is_ok()
+    }
+
+    /// Serve the next ticket, must be called once by the holder of the current ticket
+    pub(crate) fn advance(&self) {
+        self.advance_locked(&mut self.abandoned());
+    }
+
+    fn advance_locked(&self, abandoned: &mut Vec<u32>) {
+        let mut serving = self.serving.load(Relaxed).wrapping_add(1);
+        while let Some(i) = abandoned.iter().position(|&t| t == serving) {
+            abandoned.swap_remove(i);
+            serving = serving.wrapping_add(1);
+        }
+        self.serving.store(serving, Release);
+        wake_all(&self.serving);
+    }
+}
+
+struct PhaseCounts {
+    phase: u32,
+    /// Readers that started waiting during the current phase
+    waiting: u32,
+}
+
+/// Book keeping for [Policy::PhaseFair]. A phase ends every time a write lock is released,
+/// readers that were waiting during a phase are entitled to enter before the next writer
+pub(crate) struct Phases {
+    counts: Mutex<PhaseCounts>,
+    /// Readers from previous phases that have not entered yet, writers wait until this is 0
+    entitled: AtomicU32,
+}
+
+impl Phases {
+    loom_const_fn! {
+        pub(crate) fn new() -> Phases {
+            Phases {
+                counts: Mutex::new(PhaseCounts {
+                    phase: 0,
+                    waiting: 0,
+                }),
+                entitled: AtomicU32::new(0),
+            }
+        }
+    }
+
+    fn counts(&self) -> MutexGuard<'_, PhaseCounts> {
+        self.counts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Register a blocked reader, returning the phase it arrived in
+    pub(crate) fn arrive(&self) -> u32 {
+        let mut counts = self.counts();
+        counts.waiting += 1;
+        counts.phase
+    }
+
+    #[cfg(all(test, not(feature = "loom")))]
+    pub(crate) fn waiting(&self) -> u32 {
+        self.counts().waiting
+    }
+
+    /// Whether a reader that arrived in `phase` may pass a waiting writer
+    pub(crate) fn is_entitled(&self, phase: u32) -> bool {
+        self.counts().phase != phase
+    }
+
+    /// Remove a reader registered with [Self::arrive]. Returns true if it was the last entitled reader
+    pub(crate) fn leave(&self, phase: u32) -> bool {
+        let mut counts = self.counts();
+        if counts.phase == phase {
+            counts.waiting -= 1;
+            false
+        } else {
+            self.entitled.fetch_sub(1, Release) == 1
+        }
+    }
+
+    /// Whether readers from a previous phase still have to enter
+    pub(crate) fn readers_entitled(&self) -> bool {
+        self.entitled.load(Acquire) != 0
+    }
+
+    /// End the current phase, entitling all waiting readers. Called before a write lock is released
+    pub(crate) fn next_phase(&self) {
+        let mut counts = self.counts();
+        self.entitled.fetch_add(counts.waiting, Relaxed);
+        counts.waiting = 0;
+        counts.phase = counts.phase.wrapping_add(1);
+    }
+}
@@ -1,22 +1,71 @@
-use crate::{write_guard::WriteGuard, LockError, LockResult, LockState};
-use std::ops::Deref;
+use crate::{
+    deadlock::Want, hierarchy::Held, reobtained::ReleasedAt, write_guard::WriteGuard, LockError,
+    LockResult, LockState, MappedReadGuard, ReleasedRead, Reobtained, Source,
+};
+use std::{
+    mem::ManuallyDrop,
+    ops::Deref,
+    time::{Duration, Instant},
+};
 
-pub struct ReadGuard<'a, T: Sized> {
+pub struct ReadGuard<'a, T: ?Sized> {
     pub(super) state: &'a LockState,
     pub(super) data: *mut T,
+    pub(super) source: Option<Source<T>>,
+    pub(super) released_at: ReleasedAt,
+    pub(super) held: Held,
 }
 
-impl<'a, T> ReadGuard<'a, T> {
+impl<'a, T: ?Sized> ReadGuard<'a, T> {
+    /// Wrap a read lock already held on `state`, it is released when the guard is dropped
+    pub(crate) fn new(state: &'a LockState, data: *mut T, source: Option<Source<T>>) -> Self {
+        ReadGuard::converted(state, data, source, Held::new(state))
+    }
+
+    /// Same as [Self::new] for a lock taken over from a converted guard, along with its record of holding it
+    pub(crate) fn converted(
+        state: &'a LockState,
+        data: *mut T,
+        source: Option<Source<T>>,
+        held: Held,
+    ) -> Self {
+        ReadGuard {
+            state,
+            data,
+            source,
+            released_at: ReleasedAt::new(),
+            held,
+        }
+    }
+
+    /// Convert to a write guard after the state has been converted with result `res`
+    fn into_write(self, res: LockResult<()>) -> LockResult<WriteGuard<'a, T>, Self> {
+        match res {
+            Ok(()) | Err(LockError::Poisoned(_)) => (),
+            Err(e) => return Err(e.hand_back(self)),
+        }
+        // Slices were borrowed to read, borrow them again to write
+        let data = self
+            .source
+            .map_or(self.data, |source| unsafe { source.find_mut() });
+        let write = WriteGuard::converted(self.state, data, self.source, self.held.take());
+        std::mem::forget(self);
+        match res {
+            Err(LockError::Poisoned(err)) => Err(LockError::Poisoned(err.replace(write))),
+            _ => Ok(write),
+        }
+    }
+
     ///Same as [Self::to_write] but instead of blocking thread,
-    /// if a lock can not be obtained when called a [LockError::WouldBlock] is returned
-    pub fn try_to_write(self) -> LockResult<WriteGuard<'a, T>> {
-        self.state.try_to_write()?;
-        Ok(WriteGuard {
-            state: &self.state,
-            data: self.data,
-        })
+    /// if a lock can not be obtained when called [LockError::WouldBlock] hands this guard back
+    pub fn try_to_write(self) -> LockResult<WriteGuard<'a, T>, Self> {
+        let res = self.state.try_to_write();
+        self.into_write(res)
     }
 
+    /// Block until this is the only read lock, then convert to a write lock.
+    /// If another reader is already waiting to upgrade, [LockError::UpgradeConflict] hands this guard back instead,
+    /// as both would wait on each other forever. Dropping it lets the other reader upgrade
     /// ```
     /// use manual_rwlock::MrwLock;
     /// let mrw_lock = MrwLock::new(10);
@@ -26,12 +75,128 @@ impl<'a, T> ReadGuard<'a, T> {
     /// let read = write.to_read();
     /// assert_eq!(*read, 5)
     /// ```
-    pub fn to_write(self) -> LockResult<WriteGuard<'a, T>> {
-        self.state.to_write()?;
-        Ok(WriteGuard {
-            state: &self.state,
+    pub fn to_write(self) -> LockResult<WriteGuard<'a, T>, Self> {
+        let res = self.state.to_write();
+        self.into_write(res)
+    }
+
+    /// Same as [Self::to_write] but gives up with [LockError::TimedOut] after `timeout`, handing this guard back with the read lock still held
+    pub fn to_write_for(self, timeout: Duration) -> LockResult<WriteGuard<'a, T>, Self> {
+        let res = self.state.to_write_for(timeout);
+        self.into_write(res)
+    }
+
+    /// Same as [Self::to_write] but gives up with [LockError::TimedOut] once `deadline` has passed, handing this guard back with the read lock still held
+    pub fn to_write_until(self, deadline: Instant) -> LockResult<WriteGuard<'a, T>, Self> {
+        let res = self.state.to_write_until(deadline);
+        self.into_write(res)
+    }
+
+    /// Same as [Self::to_write] but waits without blocking the thread, so it can be used inside async tasks.
+    /// Dropping the future before it completes releases the read lock
+    /// ```
+    /// use manual_rwlock::MrwLock;
+    /// async fn increment(mrw_lock: &MrwLock<i32>) {
+    ///     let read = mrw_lock.read_async().await.unwrap();
+    ///     if *read < 10 {
+    ///         let Ok(mut write) = read.to_write_async().await else { return };
+    ///         *write += 1;
+    ///     }
+    /// }
+    /// ```
+    pub async fn to_write_async(self) -> LockResult<WriteGuard<'a, T>, Self> {
+        let res = self.state.to_write_async().await;
+        self.into_write(res)
+    }
+
+    /// Make a guard pointing to part of the data, such as a field, which keeps holding the read lock
+    /// ```
+    /// use manual_rwlock::MrwLock;
+    /// let mrw_lock = MrwLock::new((1, String::from("one")));
+    /// let name = mrw_lock.read().unwrap().map(|(_, name)| name);
+    /// assert_eq!(*name, "one");
+    /// ```
+    pub fn map<U: ?Sized>(self, f: impl FnOnce(&T) -> &U) -> MappedReadGuard<'a, U> {
+        let data = f(unsafe { &*self.data }) as *const U;
+        let (state, held) = (self.state, self.held.take());
+        std::mem::forget(self);
+        MappedReadGuard::converted(state, data, held)
+    }
+
+    /// Same as [Self::map] but `f` can fail by returning `None`, in which case this guard is handed back
+    /// ```
+    /// use manual_rwlock::MrwLock;
+    /// let mrw_lock = MrwLock::new(vec![1, 2, 3]);
+    /// let read = mrw_lock.read().unwrap();
+    /// let Err(read) = read.try_map(|v| v.get(3)) else { panic!() };
+    /// let Ok(last) = read.try_map(|v| v.last()) else { panic!() };
+    /// assert_eq!(*last, 3);
+    /// ```
+    pub fn try_map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&T) -> Option<&U>,
+    ) -> Result<MappedReadGuard<'a, U>, Self> {
+        let Some(data) = f(unsafe { &*self.data }).map(|data| data as *const U) else {
+            return Err(self);
+        };
+        let (state, held) = (self.state, self.held.take());
+        std::mem::forget(self);
+        Ok(MappedReadGuard::converted(state, data, held))
+    }
+
+    /// Release the lock, giving a token with no access to the data which can later reobtain it. Safe alternative to [Self::early_release]
+    /// ```
+    /// use manual_rwlock::MrwLock;
+    /// let mrw_lock = MrwLock::new(5);
+    /// let released = mrw_lock.read().unwrap().release();
+    /// *mrw_lock.write().unwrap() += 5;
+    /// let read = released.reobtain().unwrap();
+    /// assert_eq!(*read, 10);
+    /// ```
+    pub fn release(self) -> ReleasedRead<'a, T> {
+        self.held.release();
+        self.state.stats.early_released();
+        self.state.drop_read();
+        let released = ReleasedRead {
+            state: self.state,
             data: self.data,
-        })
+            source: self.source,
+        };
+        std::mem::forget(self);
+        released
+    }
+
+    /// Release the lock while `f` runs, reobtaining it before returning even if `f` panics.
+    /// Returns the result of `f` and whether the data was modified while it ran.
+    /// If the lock is poisoned in the meantime it is still reobtained, see [MrwLock::is_poisoned](crate::MrwLock::is_poisoned)
+    /// ```
+    /// use manual_rwlock::MrwLock;
+    /// let mrw_lock = MrwLock::new(5);
+    /// let mut read = mrw_lock.read().unwrap();
+    /// let ((), reobtained) = read.unlocked(|| *mrw_lock.write().unwrap() += 5);
+    /// assert!(reobtained.is_modified());
+    /// assert_eq!(*read, 10);
+    /// ```
+    pub fn unlocked<R>(&mut self, f: impl FnOnce() -> R) -> (R, Reobtained) {
+        self.held.release();
+        self.released_at.release_read(self.state);
+        self.state.drop_read();
+        let relock = Relock(self, None);
+        let res = f();
+        (res, relock.finish())
+    }
+
+    /// Same as [Self::unlocked] but threads waiting when the lock was released get it before it is reobtained,
+    /// rather than this thread taking it back first when `f` is quick.
+    /// Like waiting for a write lock, this never returns if one of them waits for another lock held by this thread
+    pub fn unlocked_fair<R>(&mut self, f: impl FnOnce() -> R) -> (R, Reobtained) {
+        let handoff = self.state.handoff();
+        self.held.release();
+        self.released_at.release_read(self.state);
+        self.state.drop_read();
+        let relock = Relock(self, Some(handoff));
+        let res = f();
+        (res, relock.finish())
     }
 
     /// Releases lock without dropping object. This can allow for a write lock to obtained and do some work after which the lock must be reobtained
@@ -54,33 +219,90 @@ impl<'a, T> ReadGuard<'a, T> {
     ///```
     ///
     pub unsafe fn early_release(&self) {
+        self.held.release();
+        self.released_at.release_read(self.state);
         self.state.drop_read();
     }
 
-    /// block until lock can be reobtained
+    /// block until lock can be reobtained, returning whether the data was modified while released.
+    /// If the lock is poisoned it is still reobtained and [LockError::Poisoned] is returned
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain(&self) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.read())
+    }
+
+    /// Same as [Self::reobtain] but gives up with [LockError::TimedOut] after `timeout`
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain_for(&self, timeout: Duration) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.read_for(timeout))
+    }
+
+    /// Same as [Self::reobtain] but gives up with [LockError::TimedOut] once `deadline` has passed
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain_until(&self, deadline: Instant) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.read_until(deadline))
+    }
+
+    /// Same as [Self::reobtain] but waits without blocking the thread.
+    /// If the future is dropped before it completes the lock is not reobtained
     /// # Safety
     /// do not use unless early release has been called. Only call at most once after each early release
-    pub unsafe fn reobtain(&self) -> Result<(), LockError> {
-        self.state.read()?;
-        Ok(())
+    pub async unsafe fn reobtain_async(&self) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.read_async().await)
     }
 
     /// attempt to reobtain lock, if not possible at this time return `LockError:WouldBlock`
     /// # Safety
     /// do not use unless early release has been called. Only call at most once after each early release
-    pub unsafe fn try_reobtain(&self) -> Result<(), LockError> {
-        self.state.try_read()?;
-        Ok(())
+    pub unsafe fn try_reobtain(&self) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.try_read())
+    }
+}
+
+/// Reobtains the read lock when dropped, waiting for the hand off first if there is one, see [ReadGuard::unlocked]
+struct Relock<'g, 'a, T: ?Sized>(&'g mut ReadGuard<'a, T>, Option<(u32, u32)>);
+
+impl<T: ?Sized> Relock<'_, '_, T> {
+    fn relock(&mut self) -> Reobtained {
+        let guard = &mut *self.0;
+        if let Some(handoff) = self.1 {
+            guard.state.wait_for_handoff(handoff);
+        }
+        guard.state.relock(Want::Read);
+        if let Some(source) = guard.source {
+            guard.data = unsafe { source.find() };
+        }
+        guard.held.record(guard.state);
+        guard.released_at.relocked(guard.state)
+    }
+
+    /// Reobtain the lock once `f` has returned
+    fn finish(self) -> Reobtained {
+        ManuallyDrop::new(self).relock()
     }
 }
 
-impl<'a, T> Drop for ReadGuard<'a, T> {
+impl<T: ?Sized> Drop for Relock<'_, '_, T> {
+    fn drop(&mut self) {
+        self.relock();
+    }
+}
+
+impl<'a, T: ?Sized> Drop for ReadGuard<'a, T> {
     fn drop(&mut self) {
         self.state.drop_read();
     }
 }
 
-impl<'a, T> Deref for ReadGuard<'a, T> {
+impl<'a, T: ?Sized> Deref for ReadGuard<'a, T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -98,17 +320,23 @@ impl<'a, T> Deref for ReadGuard<'a, T> {
 /// assert_eq!(*read2, 5);
 ///
 /// ```
-impl<'a, T> Clone for ReadGuard<'a, T> {
+impl<'a, T: ?Sized> Clone for ReadGuard<'a, T> {
     fn clone(&self) -> Self {
-        self.state.read().unwrap();
-        Self {
-            state: self.state,
-            data: self.data,
-        }
+        self.state.clone_read().unwrap();
+        ReadGuard::new(self.state, self.data, self.source)
     }
 }
 
-
-
-unsafe impl<'a,T> Send for ReadGuard<'a, T>{}
-unsafe impl<'a, T> Sync for ReadGuard<'a, T>{}
\ No newline at end of file
+/// A read guard can be cloned or converted to a write guard wherever it is sent, so needs the same bounds as sharing the lock
+/// ```compile_fail
+/// use manual_rwlock::MrwLock;
+/// use std::cell::Cell;
+/// let mrw_lock = MrwLock::new(Cell::new(0));
+/// let read = mrw_lock.read().unwrap();
+/// std::thread::scope(|s| {
+///     s.spawn(move || read.set(1));
+///     mrw_lock.read().unwrap().set(2);
+/// });
+/// ```
+unsafe impl<'a, T: ?Sized + Send + Sync> Send for ReadGuard<'a, T> {}
+unsafe impl<'a, T: ?Sized + Send + Sync> Sync for ReadGuard<'a, T> {}
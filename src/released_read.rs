@@ -0,0 +1,83 @@
+use crate::{LockError, LockResult, LockState, ReadGuard, Source};
+use std::time::{Duration, Instant};
+
+/// Read guard whose lock was released by [ReadGuard::release]. It has no access to the data until it is reobtained,
+/// which makes [ReadGuard::early_release] safe to use. Reobtaining consumes it, so each release gives back one guard
+/// # Examples
+/// ```
+/// use manual_rwlock::MrwLock;
+/// let mrw_lock = MrwLock::new(vec![1, 2, 3]);
+/// let read = mrw_lock.read_slice().unwrap();
+/// let released = read.release();
+/// mrw_lock.write().unwrap().extend([4, 5, 6]);
+/// let read = released.reobtain().unwrap();
+/// assert_eq!(*read, [1, 2, 3, 4, 5, 6]);
+/// ```
+/// The data can not be reached while released
+/// ```compile_fail
+/// use manual_rwlock::MrwLock;
+/// let mrw_lock = MrwLock::new(5);
+/// let released = mrw_lock.read().unwrap().release();
+/// assert_eq!(*released, 5);
+/// ```
+pub struct ReleasedRead<'a, T: ?Sized> {
+    pub(super) state: &'a LockState,
+    pub(super) data: *mut T,
+    pub(super) source: Option<Source<T>>,
+}
+
+impl<'a, T: ?Sized> ReleasedRead<'a, T> {
+    /// Build the guard after the lock was obtained with result `res`, finding the data again if it could have moved.
+    /// If it was not obtained this token is handed back to try again
+    fn guard(self, res: LockResult<()>) -> LockResult<ReadGuard<'a, T>, Self> {
+        let poisoned = match res {
+            Ok(()) => None,
+            Err(LockError::Poisoned(err)) => Some(err),
+            Err(e) => return Err(e.hand_back(self)),
+        };
+        self.state.stats.reobtained();
+        let data = match self.source {
+            Some(source) => unsafe { source.find() },
+            None => self.data,
+        };
+        let guard = ReadGuard::new(self.state, data, self.source);
+        match poisoned {
+            Some(err) => Err(LockError::Poisoned(err.replace(guard))),
+            None => Ok(guard),
+        }
+    }
+
+    /// block until lock can be reobtained. If the lock is poisoned it is still reobtained and [LockError::Poisoned] is returned
+    pub fn reobtain(self) -> LockResult<ReadGuard<'a, T>, Self> {
+        let res = self.state.read();
+        self.guard(res)
+    }
+
+    /// Same as [Self::reobtain] but gives up with [LockError::TimedOut] after `timeout`, handing this token back
+    pub fn reobtain_for(self, timeout: Duration) -> LockResult<ReadGuard<'a, T>, Self> {
+        let res = self.state.read_for(timeout);
+        self.guard(res)
+    }
+
+    /// Same as [Self::reobtain] but gives up with [LockError::TimedOut] once `deadline` has passed, handing this token back
+    pub fn reobtain_until(self, deadline: Instant) -> LockResult<ReadGuard<'a, T>, Self> {
+        let res = self.state.read_until(deadline);
+        self.guard(res)
+    }
+
+    /// Same as [Self::reobtain] but waits without blocking the thread
+    pub async fn reobtain_async(self) -> LockResult<ReadGuard<'a, T>, Self> {
+        let res = self.state.read_async().await;
+        self.guard(res)
+    }
+
+    /// attempt to reobtain lock, if not possible at this time [LockError::WouldBlock] hands this token back
+    pub fn try_reobtain(self) -> LockResult<ReadGuard<'a, T>, Self> {
+        let res = self.state.try_read();
+        self.guard(res)
+    }
+}
+
+/// Reobtaining gives a [ReadGuard], so the same bounds apply
+unsafe impl<T: ?Sized + Send + Sync> Send for ReleasedRead<'_, T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for ReleasedRead<'_, T> {}
@@ -0,0 +1,87 @@
+use crate::{LockError, LockResult, LockState, Source, WriteGuard};
+use std::time::{Duration, Instant};
+
+/// Write guard whose lock was released by [WriteGuard::release], see [ReleasedRead](crate::ReleasedRead)
+/// # Examples
+/// ```
+/// use manual_rwlock::MrwLock;
+/// let mrw_lock = MrwLock::new(vec![1, 2, 3]);
+/// let mut write = mrw_lock.write().unwrap();
+/// write.push(4);
+/// let released = write.release();
+/// assert_eq!(mrw_lock.read().unwrap().len(), 4);
+/// let mut write = released.reobtain().unwrap();
+/// write.push(5);
+/// assert_eq!(*write, [1, 2, 3, 4, 5]);
+/// ```
+/// A token can only be reobtained once, otherwise two guards could write to the data at the same time
+/// ```compile_fail
+/// use manual_rwlock::MrwLock;
+/// let mrw_lock = MrwLock::new(5);
+/// let released = mrw_lock.write().unwrap().release();
+/// let mut first = released.reobtain().unwrap();
+/// let mut second = released.reobtain().unwrap();
+/// *first += 1;
+/// *second += 1;
+/// ```
+pub struct ReleasedWrite<'a, T: ?Sized> {
+    pub(super) state: &'a LockState,
+    pub(super) data: *mut T,
+    pub(super) source: Option<Source<T>>,
+}
+
+impl<'a, T: ?Sized> ReleasedWrite<'a, T> {
+    /// Build the guard after the lock was obtained with result `res`, finding the data again if it could have moved.
+    /// If it was not obtained this token is handed back to try again
+    fn guard(self, res: LockResult<()>) -> LockResult<WriteGuard<'a, T>, Self> {
+        let poisoned = match res {
+            Ok(()) => None,
+            Err(LockError::Poisoned(err)) => Some(err),
+            Err(e) => return Err(e.hand_back(self)),
+        };
+        self.state.stats.reobtained();
+        let data = match self.source {
+            Some(source) => unsafe { source.find_mut() },
+            None => self.data,
+        };
+        let guard = WriteGuard::new(self.state, data, self.source);
+        match poisoned {
+            Some(err) => Err(LockError::Poisoned(err.replace(guard))),
+            None => Ok(guard),
+        }
+    }
+
+    /// block until lock can be reobtained. If the lock is poisoned it is still reobtained and [LockError::Poisoned] is returned
+    pub fn reobtain(self) -> LockResult<WriteGuard<'a, T>, Self> {
+        let res = self.state.write();
+        self.guard(res)
+    }
+
+    /// Same as [Self::reobtain] but gives up with [LockError::TimedOut] after `timeout`, handing this token back
+    pub fn reobtain_for(self, timeout: Duration) -> LockResult<WriteGuard<'a, T>, Self> {
+        let res = self.state.write_for(timeout);
+        self.guard(res)
+    }
+
+    /// Same as [Self::reobtain] but gives up with [LockError::TimedOut] once `deadline` has passed, handing this token back
+    pub fn reobtain_until(self, deadline: Instant) -> LockResult<WriteGuard<'a, T>, Self> {
+        let res = self.state.write_until(deadline);
+        self.guard(res)
+    }
+
+    /// Same as [Self::reobtain] but waits without blocking the thread
+    pub async fn reobtain_async(self) -> LockResult<WriteGuard<'a, T>, Self> {
+        let res = self.state.write_async().await;
+        self.guard(res)
+    }
+
+    /// attempt to reobtain lock, if not possible at this time [LockError::WouldBlock] hands this token back
+    pub fn try_reobtain(self) -> LockResult<WriteGuard<'a, T>, Self> {
+        let res = self.state.try_write();
+        self.guard(res)
+    }
+}
+
+/// Reobtaining gives a [WriteGuard], which can be converted to a read guard, so it needs the same bounds as sharing the lock
+unsafe impl<T: ?Sized + Send + Sync> Send for ReleasedWrite<'_, T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for ReleasedWrite<'_, T> {}
@@ -0,0 +1,74 @@
+use crate::{error::with_guard, hierarchy::Held, sync::AtomicU32, LockResult, LockState};
+use std::sync::atomic::Ordering::Relaxed;
+
+/// Whether the data was modified while a guard was released, returned when it is reobtained.
+/// Only modifications through a write guard are seen, see [LockState::mark_modified]
+/// ```
+/// use manual_rwlock::{MrwLock, Reobtained};
+/// let mrw_lock = MrwLock::new(vec![1, 2, 3]);
+/// let read = mrw_lock.read().unwrap();
+/// let sum: i32 = read.iter().sum();
+/// unsafe { read.early_release() };
+/// drop(mrw_lock.write().unwrap());
+/// // Writing nothing is not a modification, so the sum can be reused
+/// assert_eq!(unsafe { read.reobtain() }.unwrap(), Reobtained::Unchanged);
+/// unsafe { read.early_release() };
+/// mrw_lock.write().unwrap().push(4);
+/// assert!(unsafe { read.reobtain() }.unwrap().is_modified());
+/// assert_ne!(read.iter().sum::<i32>(), sum);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reobtained {
+    Unchanged,
+    Modified,
+}
+
+impl Reobtained {
+    pub fn is_modified(self) -> bool {
+        self == Reobtained::Modified
+    }
+}
+
+/// The [LockState::version] a guard was released at, to tell if the data was modified once it is reobtained
+pub(crate) struct ReleasedAt(AtomicU32);
+
+impl ReleasedAt {
+    pub(crate) fn new() -> ReleasedAt {
+        ReleasedAt(AtomicU32::new(0))
+    }
+
+    /// Record the version of a read lock as it is released early
+    pub(crate) fn release_read(&self, state: &LockState) {
+        state.stats.early_released();
+        self.0.store(state.version(), Relaxed);
+    }
+
+    /// Record the version a write lock leaves behind as it is released early
+    pub(crate) fn release_write(&self, state: &LockState) {
+        state.stats.early_released();
+        self.0.store(state.version_after_write(), Relaxed);
+    }
+
+    /// Compare the recorded version with the version of the lock once reobtained with result `res`
+    pub(crate) fn reobtained(
+        &self,
+        state: &LockState,
+        held: &Held,
+        res: LockResult<()>,
+    ) -> LockResult<Reobtained> {
+        with_guard(res, || {
+            held.record(state);
+            self.relocked(state)
+        })
+    }
+
+    /// Compare the recorded version with the version of the lock once it is held again
+    pub(crate) fn relocked(&self, state: &LockState) -> Reobtained {
+        state.stats.reobtained();
+        if state.version() == self.0.load(Relaxed) {
+            Reobtained::Unchanged
+        } else {
+            Reobtained::Modified
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Sequence counter for optimistic reads, see [MrwLock::optimistic_read](crate::MrwLock::optimistic_read) and [SeqCell](crate::SeqCell)
+use crate::sync::{fence, loom_const_fn, AtomicU32};
+use std::{
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::Ordering::{Acquire, Relaxed, Release},
+};
+
+/// Even while the data is stable, odd while a writer is modifying it
+pub(crate) struct Seq(AtomicU32);
+
+impl Seq {
+    loom_const_fn! {
+        pub(crate) fn new() -> Seq {
+            Seq(AtomicU32::new(0))
+        }
+    }
+
+    /// Start modifying the data. The caller must already have exclusive access, e.g. a write lock
+    pub(crate) fn begin_write(&self) {
+        let s = self.0.load(Relaxed);
+        self.0.store(s.wrapping_add(1), Relaxed);
+        // Readers that see any of the writes made after this also see the odd sequence
+        fence(Release);
+    }
+
+    /// Take exclusive access by making the sequence odd, for data with no other lock. Returns false if another writer has it
+    pub(crate) fn try_begin_write(&self) -> bool {
+        let s = self.0.load(Relaxed);
+        if s & 1 != 0
+            || self
+                .0
+                .compare_exchange(s, s.wrapping_add(1), Acquire, Relaxed)
+                .is_err()
+        {
+            return false;
+        }
+        fence(Release);
+        true
+    }
+
+    /// Finish modifying the data, publishing it to readers
+    pub(crate) fn end_write(&self) {
+        self.0.fetch_add(1, Release);
+    }
+
+    /// Copy the data if no writer modified it while copying
+    /// # Safety
+    /// `data` must be valid for reads, and only be modified between [Self::begin_write] and [Self::end_write]
+    pub(crate) unsafe fn read<T: Copy>(&self, data: *const T) -> Option<T> {
+        let s = self.0.load(Acquire);
+        if s & 1 != 0 {
+            return None;
+        }
+        // A writer may tear the copy, it is only used if the sequence shows there was no writer
+        let copy = ptr::read_volatile(data as *const MaybeUninit<T>);
+        fence(Acquire);
+        if self.0.load(Relaxed) != s {
+            return None;
+        }
+        Some(copy.assume_init())
+    }
+}
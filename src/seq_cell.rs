@@ -0,0 +1,104 @@
+use crate::{
+    seq::Seq,
+    sync::{loom_const_fn, spin_loop},
+};
+use std::{cell::UnsafeCell, fmt};
+
+/// # Seq Cell
+/// Cell for small `Copy` values read far more often than written, without a lock.
+/// Readers copy the value and retry if a writer changed it meanwhile, so they never write to shared memory.
+/// Writers take turns by spinning, so writes should be short and rare. See [MrwLock::optimistic_read](crate::MrwLock::optimistic_read)
+/// for the same reads of data behind a lock
+/// # Examples
+/// ```
+/// use manual_rwlock::SeqCell;
+/// let position = SeqCell::new((0.0, 0.0));
+/// std::thread::scope(|s| {
+///     s.spawn(|| position.update(|(x, y)| (x + 1.0, y + 1.0)));
+///     let (x, y) = position.get();
+///     assert_eq!(x, y);
+/// });
+/// assert_eq!(position.get(), (1.0, 1.0));
+/// ```
+pub struct SeqCell<T> {
+    seq: Seq,
+    data: UnsafeCell<T>,
+}
+
+impl<T: Copy> SeqCell<T> {
+    loom_const_fn! {
+        pub fn new(value: T) -> SeqCell<T> {
+            SeqCell {
+                seq: Seq::new(),
+                data: UnsafeCell::new(value),
+            }
+        }
+    }
+
+    pub fn get(&self) -> T {
+        loop {
+            if let Some(value) = unsafe { self.seq.read(self.data.get()) } {
+                return value;
+            }
+            spin_loop();
+        }
+    }
+
+    pub fn set(&self, value: T) {
+        self.write(|data| *data = value);
+    }
+
+    /// Set the value, returning the old one
+    pub fn replace(&self, value: T) -> T {
+        self.write(|data| std::mem::replace(data, value))
+    }
+
+    /// Replace the value with `f` of it in one step, returning the new value.
+    /// Other writers wait while `f` runs, so it should be quick
+    pub fn update(&self, f: impl FnOnce(T) -> T) -> T {
+        self.write(|data| {
+            *data = f(*data);
+            *data
+        })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    fn write<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        while !self.seq.try_begin_write() {
+            spin_loop();
+        }
+        // Ends the write even if `f` panics, readers would spin forever otherwise
+        let _end = EndWrite(&self.seq);
+        f(unsafe { &mut *self.data.get() })
+    }
+}
+
+struct EndWrite<'a>(&'a Seq);
+
+impl Drop for EndWrite<'_> {
+    fn drop(&mut self) {
+        self.0.end_write();
+    }
+}
+
+impl<T: Copy + Default> Default for SeqCell<T> {
+    fn default() -> Self {
+        SeqCell::new(T::default())
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for SeqCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SeqCell").field(&self.get()).finish()
+    }
+}
+
+/// Values are copied in and out from any thread, like a `Mutex` only `T: Send` is needed to share the cell
+unsafe impl<T: Send> Sync for SeqCell<T> {}
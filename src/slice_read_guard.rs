@@ -1,6 +1,6 @@
-use std::ops::Deref;
+use std::slice::SliceIndex;
 
-use crate::{LockResult, LockState, SliceWriteGuard};
+use crate::{MappedReadGuard, ReadGuard};
 
 /// # Slice Read Guard
 /// reduces indirection for read gaurds containing slices
@@ -14,88 +14,17 @@ use crate::{LockResult, LockState, SliceWriteGuard};
 /// assert_eq!(*slice_read, [1,2,3])
 ///
 /// ```
-pub struct SliceReadGuard<'a, T: Sized> {
-    pub(super) state: &'a LockState,
-    pub(super) data: *mut [T],
-}
-
-impl<'a, T> SliceReadGuard<'a, T> {
-    pub fn try_to_write(self) -> LockResult<SliceWriteGuard<'a, T>> {
-        self.state.try_to_write()?;
-        Ok(SliceWriteGuard {
-            state: &self.state,
-            data: self.data,
-        })
-    }
-
-    pub fn to_write(self) -> LockResult<SliceWriteGuard<'a, T>> {
-        self.state.to_write()?;
-        Ok(SliceWriteGuard {
-            state: &self.state,
-            data: self.data,
-        })
-    }
-
-    /// Releases lock without dropping object. This can allow for a write lock to obtained and do some work after which the lock must be reobtained
-    ///# Safety
-    /// Do not access contents before reobtaining lock with either reobtain or try_reobtain
-    /// # Examples
-    ///```
-    ///  use manual_rwlock::MrwLock;
-    ///
-    /// let rwlock = MrwLock::new(Vec::from([1,2,3]));
-    /// let read_rw = rwlock.read_slice().unwrap();
-    /// unsafe { read_rw.early_release() };
-    /// {
-    ///     let mut write = rwlock.write_slice().unwrap();
-    ///     write[2] = 4;
-    /// }
-    /// unsafe { read_rw.reobtain().unwrap() };
-    /// assert_eq!(*read_rw, [1,2,4]);
-    ///
-    ///```
-    ///
-    pub unsafe fn early_release(&self) {
-        self.state.drop_read();
-    }
-
-    /// block until lock can be reobtained
-    /// # Safety
-    /// do not use unless early release has been called. Only call at most once after each early release
-    pub unsafe fn reobtain(&self) -> LockResult<()> {
-        self.state.read()?;
-        Ok(())
-    }
-
-    /// attempt to reobtain lock, if not possible at this time return `LockError:WouldBlock`
-    /// # Safety
-    /// do not use unless early release has been called. Only call at most once after each early release
-    pub unsafe fn try_reobtain(&self) -> LockResult<()> {
-        self.state.try_read()?;
-        Ok(())
+pub type SliceReadGuard<'a, T> = ReadGuard<'a, [T]>;
+
+impl<'a, T> ReadGuard<'a, [T]> {
+    /// Make a guard pointing to `range` of the slice, which keeps holding the read lock. Panics if `range` is out of bounds
+    /// ```
+    /// use manual_rwlock::MrwLock;
+    /// let mrw_lock = MrwLock::new(vec![1, 2, 3, 4]);
+    /// let middle = mrw_lock.read_slice().unwrap().slice(1..3);
+    /// assert_eq!(*middle, [2, 3]);
+    /// ```
+    pub fn slice<R: SliceIndex<[T], Output = [T]>>(self, range: R) -> MappedReadGuard<'a, [T]> {
+        self.map(|slice| &slice[range])
     }
 }
-
-impl<'a, T> Drop for SliceReadGuard<'a, T> {
-    fn drop(&mut self) {
-        self.state.drop_read();
-    }
-}
-
-impl<'a, T> Deref for SliceReadGuard<'a, T> {
-    type Target = [T];
-
-    fn deref(&self) -> &Self::Target {
-        unsafe { &*self.data }
-    }
-}
-
-impl<'a, T> Clone for SliceReadGuard<'a, T> {
-    fn clone(&self) -> Self {
-        self.state.read().unwrap();
-        Self { state: self.state, data: self.data }
-    }
-}
-
-unsafe impl<'a,T> Send for SliceReadGuard<'a, T>{}
-unsafe impl<'a, T> Sync for SliceReadGuard<'a, T>{}
\ No newline at end of file
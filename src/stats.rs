@@ -0,0 +1,271 @@
+//! Contention statistics recorded per [LockState](crate::LockState) with the `stats` feature.
+//! Without it [Stats] records nothing and takes no space
+#[cfg(not(feature = "stats"))]
+pub(crate) use disabled::{Stats, WaitStart};
+#[cfg(feature = "stats")]
+pub use recording::LockStats;
+#[cfg(feature = "stats")]
+pub(crate) use recording::{Stats, WaitStart};
+
+#[cfg(feature = "stats")]
+mod recording {
+    use crate::{READERS, UPGRADABLE};
+    use std::{
+        sync::{
+            atomic::{AtomicU64, Ordering::Relaxed},
+            OnceLock,
+        },
+        time::{Duration, Instant},
+    };
+
+    /// Snapshot of the statistics of a lock since it was created or they were last reset, see [MrwLock::stats](crate::MrwLock::stats)
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct LockStats {
+        /// Read locks obtained, including cloned read guards
+        pub reads: u64,
+        pub upgradable_reads: u64,
+        /// Write locks obtained, not counting upgrades
+        pub writes: u64,
+        /// Read locks converted to write locks, including upgradable read locks
+        pub upgrades: u64,
+        /// Upgrades that gave up or returned an error, so `upgrades + failed_upgrades` were attempted
+        pub failed_upgrades: u64,
+        /// Acquisitions that found the lock held, so had to wait or returned [LockError::WouldBlock](crate::LockError::WouldBlock)
+        pub contended: u64,
+        /// Time spent waiting for the lock, whether it was obtained or not
+        pub total_wait: Duration,
+        pub max_wait: Duration,
+        /// Time the lock was held by at least one reader
+        pub total_read_hold: Duration,
+        pub max_read_hold: Duration,
+        /// Time the lock was held by a writer
+        pub total_write_hold: Duration,
+        pub max_write_hold: Duration,
+        /// Guards released early, with [ReadGuard::early_release](crate::ReadGuard::early_release), `release` or `unlocked`
+        pub early_releases: u64,
+        /// Released guards that obtained the lock again
+        pub reobtains: u64,
+    }
+
+    /// Nanoseconds since the first time was taken, so start times fit in an atomic
+    fn now() -> u64 {
+        static EPOCH: OnceLock<Instant> = OnceLock::new();
+        EPOCH.get_or_init(Instant::now).elapsed().as_nanos() as u64
+    }
+
+    /// Add a duration to a total and maximum
+    fn record(total: &AtomicU64, max: &AtomicU64, nanos: u64) {
+        total.fetch_add(nanos, Relaxed);
+        max.fetch_max(nanos, Relaxed);
+    }
+
+    /// When a thread or task started waiting for the lock
+    pub(crate) struct WaitStart(u64);
+
+    pub(crate) struct Stats {
+        reads: AtomicU64,
+        upgradable_reads: AtomicU64,
+        writes: AtomicU64,
+        upgrades: AtomicU64,
+        failed_upgrades: AtomicU64,
+        contended: AtomicU64,
+        total_wait: AtomicU64,
+        max_wait: AtomicU64,
+        total_read_hold: AtomicU64,
+        max_read_hold: AtomicU64,
+        total_write_hold: AtomicU64,
+        max_write_hold: AtomicU64,
+        early_releases: AtomicU64,
+        reobtains: AtomicU64,
+        /// When the first reader of the current read locks or the current writer obtained the lock
+        read_since: AtomicU64,
+        write_since: AtomicU64,
+    }
+
+    impl Stats {
+        pub(crate) const fn new() -> Stats {
+            Stats {
+                reads: AtomicU64::new(0),
+                upgradable_reads: AtomicU64::new(0),
+                writes: AtomicU64::new(0),
+                upgrades: AtomicU64::new(0),
+                failed_upgrades: AtomicU64::new(0),
+                contended: AtomicU64::new(0),
+                total_wait: AtomicU64::new(0),
+                max_wait: AtomicU64::new(0),
+                total_read_hold: AtomicU64::new(0),
+                max_read_hold: AtomicU64::new(0),
+                total_write_hold: AtomicU64::new(0),
+                max_write_hold: AtomicU64::new(0),
+                early_releases: AtomicU64::new(0),
+                reobtains: AtomicU64::new(0),
+                read_since: AtomicU64::new(0),
+                write_since: AtomicU64::new(0),
+            }
+        }
+
+        pub(crate) fn snapshot(&self) -> LockStats {
+            let nanos = |atomic: &AtomicU64| Duration::from_nanos(atomic.load(Relaxed));
+            LockStats {
+                reads: self.reads.load(Relaxed),
+                upgradable_reads: self.upgradable_reads.load(Relaxed),
+                writes: self.writes.load(Relaxed),
+                upgrades: self.upgrades.load(Relaxed),
+                failed_upgrades: self.failed_upgrades.load(Relaxed),
+                contended: self.contended.load(Relaxed),
+                total_wait: nanos(&self.total_wait),
+                max_wait: nanos(&self.max_wait),
+                total_read_hold: nanos(&self.total_read_hold),
+                max_read_hold: nanos(&self.max_read_hold),
+                total_write_hold: nanos(&self.total_write_hold),
+                max_write_hold: nanos(&self.max_write_hold),
+                early_releases: self.early_releases.load(Relaxed),
+                reobtains: self.reobtains.load(Relaxed),
+            }
+        }
+
+        /// Zero every statistic. Locks held at the time still record their hold time when released
+        pub(crate) fn reset(&self) {
+            for atomic in [
+                &self.reads,
+                &self.upgradable_reads,
+                &self.writes,
+                &self.upgrades,
+                &self.failed_upgrades,
+                &self.contended,
+                &self.total_wait,
+                &self.max_wait,
+                &self.total_read_hold,
+                &self.max_read_hold,
+                &self.total_write_hold,
+                &self.max_write_hold,
+                &self.early_releases,
+                &self.reobtains,
+            ] {
+                atomic.store(0, Relaxed);
+            }
+        }
+
+        /// A reader was added with `flag`, to the state word `s` from before it was added
+        pub(crate) fn read_obtained(&self, flag: u32, s: u32) {
+            if flag == UPGRADABLE {
+                self.upgradable_reads.fetch_add(1, Relaxed);
+            } else {
+                self.reads.fetch_add(1, Relaxed);
+            }
+            if s & READERS == 0 {
+                self.read_since.store(now(), Relaxed);
+            }
+        }
+
+        /// A reader left the state word `s`, the state from before it left
+        pub(crate) fn read_released(&self, s: u32) {
+            if s & READERS == 1 {
+                self.end_read_hold();
+            }
+        }
+
+        fn end_read_hold(&self) {
+            let held = now().saturating_sub(self.read_since.load(Relaxed));
+            record(&self.total_read_hold, &self.max_read_hold, held);
+        }
+
+        pub(crate) fn write_obtained(&self) {
+            self.writes.fetch_add(1, Relaxed);
+            self.write_since.store(now(), Relaxed);
+        }
+
+        /// The write lock was released, or converted to a read lock if `to_read`
+        pub(crate) fn write_released(&self, to_read: bool) {
+            let now = now();
+            let held = now.saturating_sub(self.write_since.load(Relaxed));
+            record(&self.total_write_hold, &self.max_write_hold, held);
+            if to_read {
+                self.read_since.store(now, Relaxed);
+            }
+        }
+
+        /// The only reader became the writer
+        pub(crate) fn upgraded(&self) {
+            self.upgrades.fetch_add(1, Relaxed);
+            self.end_read_hold();
+            self.write_since.store(now(), Relaxed);
+        }
+
+        pub(crate) fn upgrade_failed(&self) {
+            self.failed_upgrades.fetch_add(1, Relaxed);
+        }
+
+        /// A try acquisition returned [LockError::WouldBlock](crate::LockError::WouldBlock)
+        pub(crate) fn would_block(&self) {
+            self.contended.fetch_add(1, Relaxed);
+        }
+
+        pub(crate) fn start_wait(&self) -> WaitStart {
+            self.contended.fetch_add(1, Relaxed);
+            WaitStart(now())
+        }
+
+        pub(crate) fn stop_wait(&self, start: WaitStart) {
+            let waited = now().saturating_sub(start.0);
+            record(&self.total_wait, &self.max_wait, waited);
+        }
+
+        pub(crate) fn early_released(&self) {
+            self.early_releases.fetch_add(1, Relaxed);
+        }
+
+        pub(crate) fn reobtained(&self) {
+            self.reobtains.fetch_add(1, Relaxed);
+        }
+    }
+}
+
+#[cfg(not(feature = "stats"))]
+mod disabled {
+    pub(crate) struct WaitStart;
+
+    /// Records nothing, so every call compiles away
+    pub(crate) struct Stats;
+
+    impl Stats {
+        pub(crate) const fn new() -> Stats {
+            Stats
+        }
+
+        #[inline(always)]
+        pub(crate) fn read_obtained(&self, _flag: u32, _s: u32) {}
+
+        #[inline(always)]
+        pub(crate) fn read_released(&self, _s: u32) {}
+
+        #[inline(always)]
+        pub(crate) fn write_obtained(&self) {}
+
+        #[inline(always)]
+        pub(crate) fn write_released(&self, _to_read: bool) {}
+
+        #[inline(always)]
+        pub(crate) fn upgraded(&self) {}
+
+        #[inline(always)]
+        pub(crate) fn upgrade_failed(&self) {}
+
+        #[inline(always)]
+        pub(crate) fn would_block(&self) {}
+
+        #[inline(always)]
+        pub(crate) fn start_wait(&self) -> WaitStart {
+            WaitStart
+        }
+
+        #[inline(always)]
+        pub(crate) fn stop_wait(&self, _start: WaitStart) {}
+
+        #[inline(always)]
+        pub(crate) fn early_released(&self) {}
+
+        #[inline(always)]
+        pub(crate) fn reobtained(&self) {}
+    }
+}
@@ -0,0 +1,31 @@
+//! Synchronisation primitives used by the lock, swapped for [loom]'s model checked versions with the `loom` feature
+#[cfg(not(feature = "loom"))]
+pub(crate) use std::{
+    hint::spin_loop,
+    sync::{
+        atomic::{fence, AtomicBool, AtomicU32},
+        Mutex, MutexGuard,
+    },
+    thread::yield_now,
+};
+
+#[cfg(feature = "loom")]
+pub(crate) use loom::{
+    hint::spin_loop,
+    sync::{
+        atomic::{fence, AtomicBool, AtomicU32},
+        Mutex, MutexGuard,
+    },
+    thread::yield_now,
+};
+
+/// Declare a `const fn`, which is not const with the `loom` feature as loom's primitives can not be created in a const context
+macro_rules! loom_const_fn {
+    ($(#[$attr:meta])* $vis:vis fn $($rest:tt)*) => {
+        #[cfg(not(feature = "loom"))]
+        $(#[$attr])* $vis const fn $($rest)*
+        #[cfg(feature = "loom")]
+        $(#[$attr])* $vis fn $($rest)*
+    };
+}
+pub(crate) use loom_const_fn;
@@ -1,4 +1,16 @@
-use crate::MrwLock;
+use crate::{
+    LockError, LockResult, LockState, MrwLock, MrwLockBuilder, Policy, ReadGuard, Reobtained,
+    SeqCell, SliceReadGuard, SliceWriteGuard, UpgradableReadGuard, WaitStrategy, WriteGuard,
+    READERS, UPGRADABLE, UPGRADING, WRITER_WAITING, WRITE_LOCKED,
+};
+use std::{
+    future::Future,
+    pin::pin,
+    sync::{atomic::Ordering::Relaxed, Arc},
+    task::{Context, Poll, Wake, Waker},
+    thread::Thread,
+    time::{Duration, Instant},
+};
 
 #[test]
 fn early_release() {
@@ -35,3 +47,1805 @@ fn write_early_release() {
     assert_eq!(*write_rw, [1, 2, 3, 4, 5]);
 }
 
+fn contended_read_write(policy: Policy) {
+    contended_read_write_with(MrwLock::builder().policy(policy));
+}
+
+fn contended_read_write_with(builder: MrwLockBuilder) {
+    let rwlock = builder.build(0usize);
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    *rwlock.write().unwrap() += 1;
+                }
+            });
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    let read = rwlock.read().unwrap();
+                    assert!(*read <= 4000);
+                }
+            });
+        }
+    });
+    assert_eq!(*rwlock.read().unwrap(), 4000);
+}
+
+/// Block a writer behind a held read lock and return whether a new reader could still get in
+fn reader_passes_waiting_writer(policy: Policy) -> bool {
+    let rwlock = MrwLock::with_policy(0, policy);
+    let read = rwlock.read().unwrap();
+    std::thread::scope(|s| {
+        let writer = s.spawn(|| *rwlock.write().unwrap() += 1);
+        while match policy {
+            Policy::ReaderPreferred => false,
+            Policy::Fifo => rwlock.state.tickets.queued() == 0,
+            _ => rwlock.state.writers_waiting.load(Relaxed) == 0,
+        } {
+            std::thread::yield_now();
+        }
+        let passed = rwlock.try_read().is_ok();
+        // Clones never wait on the writer as it is waiting on this read lock
+        let read2 = read.clone();
+        drop(read2);
+        drop(read);
+        writer.join().unwrap();
+        passed
+    })
+}
+
+#[test]
+fn writer_preferred() {
+    assert!(!reader_passes_waiting_writer(Policy::WriterPreferred));
+}
+
+#[test]
+fn reader_preferred() {
+    assert!(reader_passes_waiting_writer(Policy::ReaderPreferred));
+}
+
+#[test]
+fn contended_all_policies() {
+    for policy in [
+        Policy::ReaderPreferred,
+        Policy::WriterPreferred,
+        Policy::PhaseFair,
+        Policy::Fifo,
+    ] {
+        contended_read_write(policy);
+    }
+}
+
+#[test]
+fn fifo_try_read_waits_for_queue() {
+    assert!(!reader_passes_waiting_writer(Policy::Fifo));
+}
+
+#[test]
+fn phase_fair_readers_before_next_writer() {
+    let rwlock = MrwLock::with_policy((), Policy::PhaseFair);
+    let order = std::sync::Mutex::new(Vec::new());
+    let write = rwlock.write().unwrap();
+    std::thread::scope(|s| {
+        let reader = s.spawn(|| {
+            let _read = rwlock.read().unwrap();
+            order.lock().unwrap().push("read");
+        });
+        while rwlock.state.phases.waiting() == 0 {
+            std::thread::yield_now();
+        }
+        let writer = s.spawn(|| {
+            let _write = rwlock.write().unwrap();
+            order.lock().unwrap().push("write");
+        });
+        while rwlock.state.writers_waiting.load(Relaxed) == 0 {
+            std::thread::yield_now();
+        }
+        drop(write);
+        reader.join().unwrap();
+        writer.join().unwrap();
+    });
+    assert_eq!(*order.lock().unwrap(), ["read", "write"]);
+}
+
+#[test]
+fn conversions_keep_one_lock() {
+    let rwlock = MrwLock::new(5);
+    let w = rwlock.read().unwrap().to_write().unwrap();
+    assert!(rwlock.try_read().is_err());
+    let r = w.to_read();
+    assert!(rwlock.try_write().is_err());
+    drop(r);
+    assert_eq!(rwlock.state.state.load(Relaxed), 0);
+}
+
+#[test]
+fn upgradable_read() {
+    let rwlock = MrwLock::new(5);
+    let upgradable = rwlock.upgradable_read().unwrap();
+    assert!(rwlock.try_upgradable_read().is_err());
+    let read = rwlock.read().unwrap();
+    let Err(upgradable) = upgradable.try_upgrade() else {
+        panic!("upgraded while another reader held the lock")
+    };
+    std::thread::scope(|s| {
+        s.spawn(move || drop(read));
+        let mut write = upgradable.upgrade();
+        *write += 1;
+        let upgradable = write.to_read();
+        assert_eq!(*upgradable, 6);
+    });
+    let upgradable = rwlock.upgradable_read().unwrap();
+    let read = upgradable.downgrade();
+    rwlock.try_upgradable_read().unwrap();
+    drop(read);
+    assert_eq!(rwlock.state.state.load(Relaxed), 0);
+}
+
+#[test]
+fn upgrade_blocks_new_readers() {
+    let rwlock = MrwLock::new(0);
+    let read = rwlock.read().unwrap();
+    let upgradable = rwlock.upgradable_read().unwrap();
+    std::thread::scope(|s| {
+        let upgrader = s.spawn(|| *upgradable.upgrade() += 1);
+        while rwlock.state.state.load(Relaxed) & super::UPGRADING == 0 {
+            std::thread::yield_now();
+        }
+        assert!(rwlock.try_read().is_err());
+        // A plain reader can not upgrade while the upgradable reader is waiting on it
+        assert!(matches!(
+            read.to_write(),
+            Err(LockError::UpgradeConflict(_))
+        ));
+        upgrader.join().unwrap();
+    });
+    assert_eq!(*rwlock.read().unwrap(), 1);
+}
+
+#[test]
+fn upgrade_conflict() {
+    let rwlock = MrwLock::new(0);
+    let read = rwlock.read().unwrap();
+    std::thread::scope(|s| {
+        let upgrader = s.spawn(|| *rwlock.read().unwrap().to_write().unwrap() += 1);
+        while rwlock.state.state.load(Relaxed) & super::UPGRADING == 0 {
+            std::thread::yield_now();
+        }
+        let Err(LockError::UpgradeConflict(read)) = read.to_write() else {
+            panic!("second upgrade did not conflict")
+        };
+        assert_eq!(*read, 0);
+        drop(read);
+        upgrader.join().unwrap();
+    });
+    assert_eq!(*rwlock.read().unwrap(), 1);
+}
+
+#[test]
+fn failed_upgrade_keeps_read_lock() {
+    let rwlock = Arc::new(MrwLock::new(0));
+    let other = rwlock.read().unwrap();
+    let read = rwlock.read().unwrap();
+    let Err(LockError::TimedOut(read)) = read.to_write_for(Duration::ZERO) else {
+        panic!("upgraded alongside another reader")
+    };
+    let Err(LockError::WouldBlock(read)) = read.try_to_write() else {
+        panic!("upgraded alongside another reader")
+    };
+    assert_eq!(rwlock.state.state.load(Relaxed) & READERS, 2);
+    assert!(rwlock.try_write().is_err());
+    drop(other);
+    let mut write = read.to_write_for(Duration::ZERO).unwrap();
+    *write += 1;
+    drop(write);
+
+    let other = rwlock.read().unwrap();
+    let read = rwlock.read_arc().unwrap();
+    let Err(LockError::TimedOut(read)) = read.to_write_until(Instant::now()) else {
+        panic!("upgraded alongside another reader")
+    };
+    assert_eq!(*read, 1);
+    assert_eq!(rwlock.state.state.load(Relaxed) & READERS, 2);
+    drop(other);
+    drop(read);
+    assert_eq!(rwlock.state.state.load(Relaxed), 0);
+}
+
+#[test]
+fn timeouts() {
+    let timeout = Duration::from_millis(20);
+    for policy in [
+        Policy::ReaderPreferred,
+        Policy::WriterPreferred,
+        Policy::PhaseFair,
+        Policy::Fifo,
+    ] {
+        let rwlock = MrwLock::with_policy(0, policy);
+        let read = rwlock.read().unwrap();
+        assert!(matches!(
+            rwlock.write_for(timeout),
+            Err(LockError::TimedOut(_))
+        ));
+        // A writer giving up must not keep blocking readers
+        let read2 = rwlock.read_for(timeout).unwrap();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let read3 = rwlock.read().unwrap();
+                assert!(matches!(
+                    read3.to_write_for(timeout),
+                    Err(LockError::TimedOut(_))
+                ));
+            });
+        });
+        drop(read2);
+        let mut write = read.to_write_until(Instant::now() + timeout).unwrap();
+        *write += 1;
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                assert!(matches!(
+                    rwlock.read_for(timeout),
+                    Err(LockError::TimedOut(_))
+                ))
+            });
+        });
+        drop(write);
+        assert_eq!(*rwlock.read_for(timeout).unwrap(), 1);
+        assert_eq!(rwlock.state.state.load(Relaxed), 0);
+    }
+}
+
+#[test]
+fn fifo_abandoned_ticket() {
+    let rwlock = MrwLock::with_policy(0, Policy::Fifo);
+    let read = rwlock.read().unwrap();
+    std::thread::scope(|s| {
+        let writer = s.spawn(|| *rwlock.write().unwrap() += 1);
+        while rwlock.state.tickets.queued() == 0 {
+            std::thread::yield_now();
+        }
+        // Queued behind the writer, which is waiting on `read`
+        assert!(matches!(
+            rwlock.read_for(Duration::from_millis(20)),
+            Err(LockError::TimedOut(_))
+        ));
+        drop(read);
+        writer.join().unwrap();
+    });
+    assert_eq!(*rwlock.read().unwrap(), 1);
+    assert_eq!(rwlock.state.tickets.queued(), 0);
+}
+
+#[test]
+fn contended_wait_strategies() {
+    for wait_strategy in [
+        WaitStrategy::Park,
+        WaitStrategy::Adaptive {
+            spins: 10,
+            yields: 10,
+        },
+    ] {
+        for policy in [Policy::WriterPreferred, Policy::Fifo] {
+            contended_read_write_with(
+                MrwLock::builder()
+                    .policy(policy)
+                    .wait_strategy(wait_strategy),
+            );
+        }
+    }
+}
+
+#[test]
+fn spin_wait_strategy() {
+    let rwlock = MrwLock::builder()
+        .wait_strategy(WaitStrategy::Spin)
+        .build(0);
+    let read = rwlock.read().unwrap();
+    assert!(matches!(
+        rwlock.write_for(Duration::from_millis(10)),
+        Err(LockError::TimedOut(_))
+    ));
+    std::thread::scope(|s| {
+        let writer = s.spawn(|| *rwlock.write().unwrap() += 1);
+        drop(read);
+        writer.join().unwrap();
+    });
+    assert_eq!(*rwlock.read().unwrap(), 1);
+}
+
+#[test]
+fn poison_recovers_guard() {
+    let rwlock = MrwLock::new(vec![1, 2, 3]);
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut write = rwlock.write().unwrap();
+            write.push(4);
+            panic!("poison the lock");
+        })
+        .join()
+        .unwrap_err();
+    });
+    assert!(rwlock.is_poisoned());
+    let Err(LockError::Poisoned(err)) = rwlock.read() else {
+        panic!("read did not report poison")
+    };
+    let read = err.into_inner();
+    assert_eq!(*read, [1, 2, 3, 4]);
+    let Err(LockError::Poisoned(err)) = read.to_write() else {
+        panic!("to_write did not report poison")
+    };
+    let mut write = err.into_inner();
+    write.pop();
+    drop(write);
+    let Err(LockError::Poisoned(err)) = rwlock.read_slice() else {
+        panic!("read_slice did not report poison")
+    };
+    let read = err.into_inner();
+    unsafe { read.early_release() };
+    assert!(matches!(
+        unsafe { read.reobtain() },
+        Err(LockError::Poisoned(_))
+    ));
+    drop(read);
+    // No reader was leaked by the poisoned acquisitions
+    assert_eq!(rwlock.state.state.load(Relaxed), 0);
+    rwlock.clear_poison();
+    assert_eq!(*rwlock.write().unwrap(), [1, 2, 3]);
+}
+
+#[test]
+fn poison_reason() {
+    crate::install_poison_hook();
+    let rwlock = MrwLock::new(vec![1, 2, 3]);
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let _write = rwlock.write_slice().unwrap();
+            panic!("index {} out of range", 7);
+        })
+        .join()
+        .unwrap_err();
+    });
+    assert_eq!(
+        rwlock.poison_reason().as_deref(),
+        Some("index 7 out of range")
+    );
+    let Err(LockError::Poisoned(err)) = rwlock.write_slice() else {
+        panic!("write_slice did not report poison")
+    };
+    assert_eq!(err.reason(), Some("index 7 out of range"));
+    let write = err.into_inner();
+    rwlock.clear_poison();
+    assert_eq!(rwlock.poison_reason(), None);
+    write.poison("bad checksum");
+    let read = write.to_read();
+    let Err(LockError::Poisoned(err)) = read.to_write() else {
+        panic!("to_write did not report poison")
+    };
+    assert_eq!(err.reason(), Some("bad checksum"));
+    drop(err);
+    rwlock.clear_poison();
+    assert!(rwlock.read().is_ok());
+
+    // Panicking after poisoning keeps the reason given
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let write = rwlock.write().unwrap();
+            write.poison("half written");
+            panic!("gave up");
+        })
+        .join()
+        .unwrap_err();
+    });
+    assert_eq!(rwlock.poison_reason().as_deref(), Some("half written"));
+}
+
+#[test]
+fn try_write_excludes_readers() {
+    let rwlock = MrwLock::new(vec![1, 2, 3]);
+    let write = rwlock.try_write().unwrap();
+    assert!(matches!(rwlock.try_read(), Err(LockError::WouldBlock(_))));
+    assert!(matches!(
+        rwlock.try_write_slice(),
+        Err(LockError::WouldBlock(_))
+    ));
+    drop(write);
+    let write = rwlock.try_write_slice().unwrap();
+    assert!(matches!(
+        rwlock.try_read_slice(),
+        Err(LockError::WouldBlock(_))
+    ));
+    assert!(matches!(
+        rwlock.read_for(Duration::from_millis(1)),
+        Err(LockError::TimedOut(_))
+    ));
+    drop(write);
+    assert_eq!(rwlock.state.state.load(Relaxed), 0);
+}
+
+/// xorshift64, so a failing sequence can be replayed from its seed
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+/// How an operation that can wait is called. Blocking calls are only made when the model says they succeed
+#[derive(Clone, Copy)]
+enum Mode {
+    Try,
+    Timed,
+    Blocking,
+}
+
+const TICK: Duration = Duration::from_millis(1);
+
+impl Mode {
+    fn pick(rng: &mut Rng, succeeds: bool) -> Mode {
+        match rng.below(8) {
+            0 => Mode::Timed,
+            1..=3 => Mode::Try,
+            _ if succeeds => Mode::Blocking,
+            _ => Mode::Try,
+        }
+    }
+}
+
+enum Outcome<G, R> {
+    Obtained(G),
+    /// Would block or timed out, any guard being converted was released
+    Blocked,
+    /// [LockError::UpgradeConflict] handing back the guard
+    Conflict(R),
+}
+
+impl<G, R> Outcome<G, R> {
+    /// Poisoned acquisitions are obtained, checking the lock was expected to be poisoned
+    fn new(res: LockResult<G, R>, poisoned: bool) -> Outcome<G, R> {
+        match res {
+            Ok(guard) => {
+                assert!(!poisoned, "poisoned lock obtained without error");
+                Outcome::Obtained(guard)
+            }
+            Err(LockError::Poisoned(err)) => {
+                assert!(poisoned, "lock reported poisoned");
+                Outcome::Obtained(err.into_inner())
+            }
+            Err(LockError::WouldBlock(_) | LockError::TimedOut(_)) => Outcome::Blocked,
+            Err(LockError::UpgradeConflict(guard)) => Outcome::Conflict(guard),
+            Err(LockError::TooManyReaders) => panic!("too many readers"),
+        }
+    }
+
+    fn map<H, S>(self, g: impl FnOnce(G) -> H, r: impl FnOnce(R) -> S) -> Outcome<H, S> {
+        match self {
+            Outcome::Obtained(guard) => Outcome::Obtained(g(guard)),
+            Outcome::Blocked => Outcome::Blocked,
+            Outcome::Conflict(guard) => Outcome::Conflict(r(guard)),
+        }
+    }
+
+    fn obtained(self, expected: bool) -> Option<G> {
+        match self {
+            Outcome::Obtained(guard) => {
+                assert!(expected, "obtained a lock the model does not allow");
+                Some(guard)
+            }
+            _ => {
+                assert!(!expected, "failed to obtain a lock the model allows");
+                None
+            }
+        }
+    }
+}
+
+type Data = Vec<u32>;
+
+enum Read<'a> {
+    Plain(ReadGuard<'a, Data>),
+    Slice(SliceReadGuard<'a, u32>),
+}
+
+enum Write<'a> {
+    Plain(WriteGuard<'a, Data>),
+    Slice(SliceWriteGuard<'a, u32>),
+}
+
+impl<'a> Read<'a> {
+    fn acquire(
+        lock: &'a MrwLock<Data>,
+        slice: bool,
+        mode: Mode,
+        poisoned: bool,
+    ) -> Outcome<Self, ()> {
+        if slice {
+            let res = match mode {
+                Mode::Try => lock.try_read_slice(),
+                Mode::Timed => lock.read_slice_for(TICK),
+                Mode::Blocking => lock.read_slice(),
+            };
+            Outcome::new(res, poisoned).map(Read::Slice, |r| r)
+        } else {
+            let res = match mode {
+                Mode::Try => lock.try_read(),
+                Mode::Timed => lock.read_for(TICK),
+                Mode::Blocking => lock.read(),
+            };
+            Outcome::new(res, poisoned).map(Read::Plain, |r| r)
+        }
+    }
+
+    fn value(&self) -> u32 {
+        match self {
+            Read::Plain(guard) => guard[0],
+            Read::Slice(guard) => guard[0],
+        }
+    }
+
+    fn clone(&self) -> Self {
+        match self {
+            Read::Plain(guard) => Read::Plain(guard.clone()),
+            Read::Slice(guard) => Read::Slice(guard.clone()),
+        }
+    }
+
+    fn into_write(self, mode: Mode, poisoned: bool) -> Outcome<Write<'a>, Self> {
+        match self {
+            Read::Plain(guard) => {
+                let res = match mode {
+                    Mode::Try => guard.try_to_write(),
+                    Mode::Timed => guard.to_write_for(TICK),
+                    Mode::Blocking => guard.to_write(),
+                };
+                Outcome::new(res, poisoned).map(Write::Plain, Read::Plain)
+            }
+            Read::Slice(guard) => {
+                let res = match mode {
+                    Mode::Try => guard.try_to_write(),
+                    Mode::Timed => guard.to_write_for(TICK),
+                    Mode::Blocking => guard.to_write(),
+                };
+                Outcome::new(res, poisoned).map(Write::Slice, Read::Slice)
+            }
+        }
+    }
+
+    unsafe fn early_release(&self) {
+        match self {
+            Read::Plain(guard) => guard.early_release(),
+            Read::Slice(guard) => guard.early_release(),
+        }
+    }
+
+    unsafe fn reobtain(&self, mode: Mode) -> LockResult<Reobtained> {
+        match (self, mode) {
+            (Read::Plain(guard), Mode::Try) => guard.try_reobtain(),
+            (Read::Plain(guard), Mode::Timed) => guard.reobtain_for(TICK),
+            (Read::Plain(guard), Mode::Blocking) => guard.reobtain(),
+            (Read::Slice(guard), Mode::Try) => guard.try_reobtain(),
+            (Read::Slice(guard), Mode::Timed) => guard.reobtain_for(TICK),
+            (Read::Slice(guard), Mode::Blocking) => guard.reobtain(),
+        }
+    }
+}
+
+impl<'a> Write<'a> {
+    fn acquire(
+        lock: &'a MrwLock<Data>,
+        slice: bool,
+        mode: Mode,
+        poisoned: bool,
+    ) -> Outcome<Self, ()> {
+        if slice {
+            let res = match mode {
+                Mode::Try => lock.try_write_slice(),
+                Mode::Timed => lock.write_slice_for(TICK),
+                Mode::Blocking => lock.write_slice(),
+            };
+            Outcome::new(res, poisoned).map(Write::Slice, |r| r)
+        } else {
+            let res = match mode {
+                Mode::Try => lock.try_write(),
+                Mode::Timed => lock.write_for(TICK),
+                Mode::Blocking => lock.write(),
+            };
+            Outcome::new(res, poisoned).map(Write::Plain, |r| r)
+        }
+    }
+
+    fn value(&mut self) -> &mut u32 {
+        match self {
+            Write::Plain(guard) => &mut guard[0],
+            Write::Slice(guard) => &mut guard[0],
+        }
+    }
+
+    fn into_read(self) -> Read<'a> {
+        match self {
+            Write::Plain(guard) => Read::Plain(guard.to_read()),
+            Write::Slice(guard) => Read::Slice(guard.to_read()),
+        }
+    }
+
+    fn poison(&self) {
+        match self {
+            Write::Plain(guard) => guard.poison("model"),
+            Write::Slice(guard) => guard.poison("model"),
+        }
+    }
+
+    unsafe fn early_release(&self) {
+        match self {
+            Write::Plain(guard) => guard.early_release(),
+            Write::Slice(guard) => guard.early_release(),
+        }
+    }
+
+    unsafe fn reobtain(&self, mode: Mode) -> LockResult<Reobtained> {
+        match (self, mode) {
+            (Write::Plain(guard), Mode::Try) => guard.try_reobtain(),
+            (Write::Plain(guard), Mode::Timed) => guard.reobtain_for(TICK),
+            (Write::Plain(guard), Mode::Blocking) => guard.reobtain(),
+            (Write::Slice(guard), Mode::Try) => guard.try_reobtain(),
+            (Write::Slice(guard), Mode::Timed) => guard.reobtain_for(TICK),
+            (Write::Slice(guard), Mode::Blocking) => guard.reobtain(),
+        }
+    }
+}
+
+/// Reference model of which locks are held
+#[derive(Default)]
+struct Model {
+    /// Read guards, not counting the upgradable one
+    readers: u32,
+    upgradable: bool,
+    writer: bool,
+    poisoned: bool,
+    value: u32,
+}
+
+impl Model {
+    fn can_read(&self) -> bool {
+        !self.writer
+    }
+
+    fn can_upgradable_read(&self) -> bool {
+        !self.writer && !self.upgradable
+    }
+
+    fn can_write(&self) -> bool {
+        !self.writer && !self.upgradable && self.readers == 0
+    }
+
+    fn check(&self, state: &LockState) {
+        let s = state.state.load(Relaxed);
+        let locks = if self.writer {
+            WRITE_LOCKED
+        } else {
+            self.readers + self.upgradable as u32
+        };
+        assert_eq!(s & READERS, locks, "lock count");
+        assert_eq!(s & UPGRADABLE != 0, self.upgradable, "upgradable flag");
+        assert_eq!(s & (UPGRADING | WRITER_WAITING), 0, "nothing is waiting");
+        assert_eq!(state.is_poisoned(), self.poisoned, "poisoned");
+    }
+}
+
+/// Run `steps` random operations on a lock with `policy`, checking the lock against [Model] after each one
+fn conformance(policy: Policy, seed: u64, steps: usize) {
+    let lock = MrwLock::with_policy(vec![0], policy);
+    let mut rng = Rng::new(seed);
+    let mut model = Model::default();
+    let mut reads: Vec<Read> = Vec::new();
+    let mut released_reads: Vec<Read> = Vec::new();
+    let mut upgradable: Option<UpgradableReadGuard<Data>> = None;
+    let mut write: Option<Write> = None;
+    let mut released_writes: Vec<Write> = Vec::new();
+    for _ in 0..steps {
+        match rng.below(16) {
+            0 => {
+                let mode = Mode::pick(&mut rng, model.can_read());
+                let slice = rng.below(2) == 0;
+                let read = Read::acquire(&lock, slice, mode, model.poisoned);
+                if let Some(read) = read.obtained(model.can_read()) {
+                    model.readers += 1;
+                    reads.push(read);
+                }
+            }
+            1 => {
+                let mode = Mode::pick(&mut rng, model.can_upgradable_read());
+                let res = match mode {
+                    Mode::Try => lock.try_upgradable_read(),
+                    Mode::Timed => lock.upgradable_read_for(TICK),
+                    Mode::Blocking => lock.upgradable_read(),
+                };
+                let guard = Outcome::new(res, model.poisoned).obtained(model.can_upgradable_read());
+                if let Some(guard) = guard {
+                    model.upgradable = true;
+                    upgradable = Some(guard);
+                }
+            }
+            2 => {
+                let mode = Mode::pick(&mut rng, model.can_write());
+                let slice = rng.below(2) == 0;
+                let guard = Write::acquire(&lock, slice, mode, model.poisoned);
+                if let Some(guard) = guard.obtained(model.can_write()) {
+                    model.writer = true;
+                    write = Some(guard);
+                }
+            }
+            3 if !reads.is_empty() => {
+                let read = reads[rng.below(reads.len())].clone();
+                model.readers += 1;
+                reads.push(read);
+            }
+            4 if !reads.is_empty() => {
+                let read = reads.swap_remove(rng.below(reads.len()));
+                let succeeds = model.readers == 1 && !model.upgradable;
+                let mode = Mode::pick(&mut rng, succeeds);
+                // Only waiting conversions conflict with an upgradable reader, trying just sees another lock
+                let conflicts = model.upgradable && !matches!(mode, Mode::Try);
+                match read.into_write(mode, model.poisoned) {
+                    Outcome::Obtained(guard) => {
+                        assert!(succeeds, "upgraded while other locks are held");
+                        model.readers = 0;
+                        model.writer = true;
+                        write = Some(guard);
+                    }
+                    Outcome::Blocked => {
+                        assert!(!succeeds && !conflicts, "upgrade blocked");
+                        model.readers -= 1;
+                    }
+                    Outcome::Conflict(read) => {
+                        assert!(conflicts, "conflict without another upgrader");
+                        reads.push(read);
+                    }
+                }
+            }
+            5 if upgradable.is_some() => {
+                let guard = upgradable.take().unwrap();
+                let succeeds = model.readers == 0;
+                let res = if rng.below(2) == 0 {
+                    guard.try_upgrade()
+                } else if succeeds {
+                    Ok(guard.upgrade())
+                } else {
+                    Err(guard)
+                };
+                match res {
+                    Ok(guard) => {
+                        assert!(succeeds, "upgraded while readers are held");
+                        model.upgradable = false;
+                        model.writer = true;
+                        write = Some(Write::Plain(guard));
+                    }
+                    Err(guard) => {
+                        assert!(!succeeds, "failed to upgrade the only lock");
+                        upgradable = Some(guard);
+                    }
+                }
+            }
+            6 if upgradable.is_some() => {
+                let read = upgradable.take().unwrap().downgrade();
+                model.upgradable = false;
+                model.readers += 1;
+                reads.push(Read::Plain(read));
+            }
+            7 if write.is_some() => {
+                reads.push(write.take().unwrap().into_read());
+                model.writer = false;
+                model.readers = 1;
+            }
+            8 if !reads.is_empty() => {
+                reads.swap_remove(rng.below(reads.len()));
+                model.readers -= 1;
+            }
+            9 if upgradable.is_some() => {
+                upgradable = None;
+                model.upgradable = false;
+            }
+            10 if write.is_some() => {
+                write = None;
+                model.writer = false;
+            }
+            11 if !reads.is_empty() => {
+                let read = reads.swap_remove(rng.below(reads.len()));
+                unsafe { read.early_release() };
+                model.readers -= 1;
+                released_reads.push(read);
+            }
+            12 if !released_reads.is_empty() => {
+                let i = rng.below(released_reads.len());
+                let mode = Mode::pick(&mut rng, model.can_read());
+                let res = unsafe { released_reads[i].reobtain(mode) };
+                if Outcome::new(res, model.poisoned)
+                    .obtained(model.can_read())
+                    .is_some()
+                {
+                    model.readers += 1;
+                    reads.push(released_reads.swap_remove(i));
+                }
+            }
+            13 if write.is_some() => {
+                let guard = write.take().unwrap();
+                unsafe { guard.early_release() };
+                model.writer = false;
+                released_writes.push(guard);
+            }
+            14 if !released_writes.is_empty() => {
+                let i = rng.below(released_writes.len());
+                let mode = Mode::pick(&mut rng, model.can_write());
+                let res = unsafe { released_writes[i].reobtain(mode) };
+                if Outcome::new(res, model.poisoned)
+                    .obtained(model.can_write())
+                    .is_some()
+                {
+                    model.writer = true;
+                    write = Some(released_writes.swap_remove(i));
+                }
+            }
+            15 => match write.as_mut() {
+                Some(guard) if rng.below(4) == 0 => {
+                    guard.poison();
+                    model.poisoned = true;
+                }
+                Some(guard) => {
+                    *guard.value() += 1;
+                    model.value += 1;
+                }
+                None => {
+                    lock.clear_poison();
+                    model.poisoned = false;
+                }
+            },
+            _ => continue,
+        }
+        model.check(&lock.state);
+        for read in &reads {
+            assert_eq!(read.value(), model.value);
+        }
+        if let Some(guard) = &upgradable {
+            assert_eq!(guard[0], model.value);
+        }
+        if let Some(guard) = &mut write {
+            assert_eq!(*guard.value(), model.value);
+        }
+    }
+    drop((reads, upgradable, write));
+    for read in released_reads {
+        let _ = unsafe { read.reobtain(Mode::Blocking) };
+    }
+    for write in released_writes {
+        let _ = unsafe { write.reobtain(Mode::Blocking) };
+    }
+    assert_eq!(lock.state.state.load(Relaxed), 0);
+}
+
+#[test]
+fn state_machine_conformance() {
+    for policy in [
+        Policy::ReaderPreferred,
+        Policy::WriterPreferred,
+        Policy::PhaseFair,
+        Policy::Fifo,
+    ] {
+        for seed in 0..64 {
+            conformance(policy, seed, 200);
+        }
+    }
+}
+
+/// Minimal executor, parks the thread until the future wakes it
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(Thread);
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+    let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
+
+/// Poll `future` once, returning it if it is still pending
+fn poll_once<F: Future + Unpin>(mut future: F) -> Result<F::Output, F> {
+    match std::pin::Pin::new(&mut future).poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(output) => Ok(output),
+        Poll::Pending => Err(future),
+    }
+}
+
+#[test]
+fn async_contended_all_policies() {
+    for policy in [
+        Policy::ReaderPreferred,
+        Policy::WriterPreferred,
+        Policy::PhaseFair,
+        Policy::Fifo,
+    ] {
+        let rwlock = MrwLock::with_policy(vec![0usize], policy);
+        std::thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..500 {
+                        block_on(async { rwlock.write_async().await.unwrap()[0] += 1 });
+                        block_on(async { rwlock.write_slice_async().await.unwrap()[0] += 1 });
+                    }
+                });
+                s.spawn(|| {
+                    for _ in 0..500 {
+                        block_on(async {
+                            assert!(rwlock.read_async().await.unwrap()[0] <= 4000);
+                            assert!(rwlock.read_slice_async().await.unwrap()[0] <= 4000);
+                        });
+                    }
+                });
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        rwlock.write().unwrap()[0] += 1;
+                        assert!(rwlock.read().unwrap()[0] <= 4000);
+                    }
+                });
+            }
+        });
+        assert_eq!(rwlock.read().unwrap()[0], 4000, "{policy:?}");
+    }
+}
+
+#[test]
+fn async_waits_for_sync_writer() {
+    let rwlock = MrwLock::new(0);
+    let mut write = rwlock.write().unwrap();
+    std::thread::scope(|s| {
+        let reader = s.spawn(|| block_on(async { *rwlock.read_async().await.unwrap() }));
+        while rwlock.state.state.load(Relaxed) & crate::ASYNC_WAITING == 0 {
+            std::thread::yield_now();
+        }
+        *write = 5;
+        drop(write);
+        assert_eq!(reader.join().unwrap(), 5);
+    });
+}
+
+#[test]
+fn async_cancel() {
+    for policy in [
+        Policy::ReaderPreferred,
+        Policy::WriterPreferred,
+        Policy::PhaseFair,
+        Policy::Fifo,
+    ] {
+        let rwlock = MrwLock::with_policy(0, policy);
+        let read = rwlock.read().unwrap();
+        let Err(write) = poll_once(Box::pin(rwlock.write_async())) else {
+            panic!("write obtained while read locked")
+        };
+        drop(write);
+        // The cancelled writer no longer holds back readers or its place in the queue
+        assert_eq!(rwlock.state.writers_waiting.load(Relaxed), 0);
+        assert_eq!(rwlock.state.tickets.queued(), 0);
+        assert!(rwlock.try_read().is_ok(), "{policy:?}");
+        drop(read);
+
+        let write = rwlock.write().unwrap();
+        let Err(read) = poll_once(Box::pin(rwlock.read_async())) else {
+            panic!("read obtained while write locked")
+        };
+        drop(read);
+        assert_eq!(rwlock.state.phases.waiting(), 0);
+        drop(write);
+        assert!(rwlock.try_write().is_ok(), "{policy:?}");
+        assert_eq!(rwlock.state.state.load(Relaxed) & READERS, 0);
+    }
+}
+
+#[test]
+fn async_to_write() {
+    let rwlock = MrwLock::new(vec![1, 2, 3]);
+    let read = rwlock.read().unwrap();
+    let upgrading = rwlock.read_slice().unwrap();
+    let Err(upgrade) = poll_once(Box::pin(upgrading.to_write_async())) else {
+        panic!("upgraded while another reader is held")
+    };
+    assert_ne!(rwlock.state.state.load(Relaxed) & UPGRADING, 0);
+    assert!(rwlock.try_read().is_err());
+    let Ok(Err(LockError::UpgradeConflict(read))) = poll_once(Box::pin(read.to_write_async()))
+    else {
+        panic!("second upgrade did not conflict")
+    };
+    std::thread::scope(|s| {
+        let upgraded = s.spawn(|| {
+            let mut write = block_on(upgrade).unwrap();
+            write[0] = 4;
+        });
+        drop(read);
+        upgraded.join().unwrap();
+    });
+    assert_eq!(*rwlock.read().unwrap(), [4, 2, 3]);
+
+    // Dropping a pending upgrade lets readers back in and releases its read lock
+    let read = rwlock.read().unwrap();
+    let upgrading = rwlock.read().unwrap();
+    drop(poll_once(Box::pin(upgrading.to_write_async())));
+    assert_eq!(rwlock.state.state.load(Relaxed) & (READERS | UPGRADING), 1);
+    drop(read);
+}
+
+#[test]
+fn async_reobtain() {
+    let rwlock = MrwLock::new(0);
+    let read = rwlock.read().unwrap();
+    unsafe { read.early_release() };
+    let mut write = rwlock.write().unwrap();
+    let Err(reobtain) = poll_once(Box::pin(unsafe { read.reobtain_async() })) else {
+        panic!("reobtained while write locked")
+    };
+    *write = 5;
+    unsafe { write.early_release() };
+    block_on(reobtain).unwrap();
+    assert_eq!(*read, 5);
+    let Err(reobtain) = poll_once(Box::pin(unsafe { write.reobtain_async() })) else {
+        panic!("reobtained write lock while read locked")
+    };
+    drop(read);
+    block_on(reobtain).unwrap();
+    *write = 6;
+    drop(write);
+    assert_eq!(*rwlock.read().unwrap(), 6);
+}
+
+#[test]
+fn guards_held_across_await_are_send() {
+    fn assert_send<F: Future + Send>(future: F) -> F {
+        future
+    }
+    let rwlock = MrwLock::new(vec![1]);
+    let future = assert_send(async {
+        let read = rwlock.read_async().await.unwrap();
+        unsafe { read.early_release() };
+        std::future::ready(()).await;
+        unsafe { read.reobtain_async().await.unwrap() };
+        let Ok(mut write) = read.to_write_async().await else {
+            panic!()
+        };
+        std::future::ready(()).await;
+        write.push(2);
+    });
+    std::thread::scope(|s| s.spawn(|| block_on(future)).join().unwrap());
+    assert_eq!(*rwlock.read().unwrap(), [1, 2]);
+}
+
+#[test]
+fn version_counts_modifying_writes() {
+    let rwlock = MrwLock::new(vec![1, 2, 3]);
+    assert_eq!(rwlock.version(), 0);
+    let write = rwlock.write().unwrap();
+    assert_eq!(write.len(), 3);
+    drop(write);
+    assert_eq!(rwlock.version(), 0);
+    rwlock.write().unwrap().push(4);
+    assert_eq!(rwlock.version(), 1);
+    let mut write = rwlock.try_write_slice().unwrap();
+    write[0] = 0;
+    let read = write.to_read();
+    assert_eq!(rwlock.version(), 2);
+    drop(read);
+    let mut write = rwlock.write().unwrap();
+    write.pop();
+    unsafe { write.early_release() };
+    assert_eq!(rwlock.version(), 3);
+    unsafe { write.reobtain().unwrap() };
+    drop(write);
+    assert_eq!(rwlock.version(), 3);
+}
+
+#[test]
+fn changes() {
+    let rwlock = MrwLock::new(0);
+    let mut changes = rwlock.changes();
+    *rwlock.write().unwrap() += 1;
+    *rwlock.write().unwrap() += 1;
+    // Changes made before polling are merged
+    assert_eq!(block_on(changes.next()), Some(2));
+    let Err(next) = poll_once(Box::pin(changes.next())) else {
+        panic!("change yielded without a write")
+    };
+    drop(next);
+    std::thread::scope(|s| {
+        let watcher = s.spawn(|| block_on(changes.next()));
+        while rwlock.state.version.load(Relaxed) & crate::CHANGE_WAITING == 0 {
+            std::thread::yield_now();
+        }
+        *rwlock.write().unwrap() += 1;
+        assert_eq!(watcher.join().unwrap(), Some(3));
+    });
+    assert_eq!(*rwlock.read().unwrap(), 3);
+}
+
+#[test]
+fn arc_guards() {
+    let rwlock = Arc::new(MrwLock::new(vec![1, 2, 3]));
+    let read = rwlock.read_arc().unwrap();
+    let read2 = read.clone();
+    assert!(Arc::ptr_eq(read.lock(), &rwlock));
+    // The guard keeps the lock alive after every other handle is gone
+    let weak = Arc::downgrade(&rwlock);
+    drop(rwlock);
+    let Err(LockError::WouldBlock(_)) = read2.try_to_write() else {
+        panic!("upgraded alongside another reader")
+    };
+    let handle = std::thread::spawn(move || {
+        assert!(weak.upgrade().is_some());
+        unsafe { read.early_release() };
+        unsafe { read.reobtain().unwrap() };
+        let mut write = read.to_write().unwrap();
+        write.push(4);
+        write.to_read()
+    });
+    let read = handle.join().unwrap();
+    assert_eq!(*read, [1, 2, 3, 4]);
+    let rwlock = read.lock().clone();
+    drop(read);
+
+    let mut write = rwlock.write_slice_arc().unwrap();
+    write[0] = 0;
+    write.poison("arc");
+    let read = write.to_read();
+    assert_eq!(*read, [0, 2, 3, 4]);
+    drop(read);
+    let Err(LockError::Poisoned(err)) = rwlock.read_slice_arc::<i32>() else {
+        panic!()
+    };
+    assert_eq!(err.reason(), Some("arc"));
+    let read = err.into_inner();
+    let Err(LockError::Poisoned(err)) = read.to_write() else {
+        panic!()
+    };
+    let mut write = err.into_inner();
+    write[1] = 0;
+    rwlock.clear_poison();
+    drop(write);
+    assert_eq!(*rwlock.write_arc().unwrap(), [0, 0, 3, 4]);
+    assert_eq!(Arc::strong_count(&rwlock), 1);
+}
+
+#[test]
+fn mapped_guards() {
+    struct Data {
+        id: u32,
+        values: Vec<i32>,
+    }
+    let rwlock = MrwLock::new(Data {
+        id: 1,
+        values: vec![1, 2, 3, 4],
+    });
+    let id = rwlock.read().unwrap().map(|data| &data.id);
+    let id2 = id.clone();
+    // Both clones hold a read lock
+    assert!(matches!(rwlock.try_write(), Err(LockError::WouldBlock(_))));
+    drop(id);
+    unsafe { id2.early_release() };
+    rwlock.write().unwrap().id = 2;
+    unsafe { id2.reobtain().unwrap() };
+    assert_eq!(*id2, 2);
+    drop(id2);
+
+    let Err(read) = rwlock.read().unwrap().try_map(|data| data.values.get(4)) else {
+        panic!()
+    };
+    let middle = read
+        .map(|data| data.values.as_slice())
+        .map(|values| &values[1..3]);
+    assert_eq!(*middle, [2, 3]);
+    assert!(rwlock.try_write().is_err());
+    drop(middle);
+
+    let version = rwlock.version();
+    let mut id = rwlock.write().unwrap().map(|data| &mut data.id);
+    *id += 1;
+    let id = id.to_read();
+    assert!(matches!(rwlock.try_write(), Err(LockError::WouldBlock(_))));
+    drop(id);
+    assert_eq!(rwlock.version(), version + 1);
+    // A failed projection is not a modification
+    let Err(write) = rwlock
+        .write()
+        .unwrap()
+        .try_map(|data| data.values.get_mut(4))
+    else {
+        panic!()
+    };
+    drop(write);
+    assert_eq!(rwlock.version(), version + 1);
+    let Err(mut write) = rwlock
+        .write()
+        .unwrap()
+        .try_map(|data| data.values.get_mut(4))
+    else {
+        panic!()
+    };
+    write.values.push(5);
+    drop(write);
+    assert_eq!(rwlock.version(), version + 2);
+
+    let rwlock = MrwLock::new(vec![1, 2, 3, 4]);
+    let mut tail = rwlock.write_slice().unwrap().slice(2..);
+    tail[0] = 0;
+    tail.poison("mapped");
+    drop(tail);
+    let Err(LockError::Poisoned(err)) = rwlock.read_slice::<i32>() else {
+        panic!()
+    };
+    assert_eq!(*err.into_inner().slice(..), [1, 2, 0, 4]);
+    rwlock.clear_poison();
+    let panicked = std::thread::scope(|s| {
+        s.spawn(|| rwlock.read_slice::<i32>().unwrap().slice(5..))
+            .join()
+            .is_err()
+    });
+    // The read lock is released while unwinding
+    assert!(panicked);
+    assert!(rwlock.try_write().is_ok());
+}
+
+#[test]
+fn unsized_locks() {
+    trait Counter {
+        fn add(&mut self) -> u32;
+    }
+    struct Count(u32);
+    impl Counter for Count {
+        fn add(&mut self) -> u32 {
+            self.0 += 1;
+            self.0
+        }
+    }
+    let counter: Arc<MrwLock<dyn Counter + Send + Sync>> = Arc::new(MrwLock::new(Count(0)));
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let counter = counter.clone();
+            std::thread::spawn(move || {
+                let mut write = counter.write_arc().unwrap();
+                write.add()
+            })
+        })
+        .collect();
+    let mut counts: Vec<u32> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    counts.sort();
+    assert_eq!(counts, [1, 2, 3, 4]);
+
+    // Every element is dropped exactly once, by the lock
+    let drops = Arc::new(());
+    let values = Box::<MrwLock<[Arc<()>]>>::from(vec![drops.clone(); 5]);
+    assert_eq!(Arc::strong_count(&drops), 6);
+    let values: Arc<MrwLock<[Arc<()>]>> = values.into();
+    let read = values.read_arc().unwrap();
+    assert_eq!(read.len(), 5);
+    let Ok(mut write) = read.to_write() else {
+        panic!()
+    };
+    write[0] = Arc::new(());
+    let read = write.to_read();
+    assert_eq!(Arc::strong_count(&drops), 5);
+    drop(read);
+    drop(values);
+    assert_eq!(Arc::strong_count(&drops), 1);
+
+    let empty = Box::<MrwLock<[u64]>>::from(Vec::new());
+    assert!(empty.read().unwrap().is_empty());
+    let zsts = Box::<MrwLock<[()]>>::from(vec![(); 3]);
+    assert_eq!(zsts.read().unwrap().len(), 3);
+
+    let boxed: Box<MrwLock<[u8]>> = Box::new(MrwLock::new([1, 2, 3]));
+    let upgradable = boxed.upgradable_read().unwrap();
+    let mut write = upgradable.upgrade();
+    write.reverse();
+    drop(write);
+    assert_eq!(*boxed.read().unwrap().slice(..2), [3, 2]);
+}
+
+#[test]
+fn send_sync_bounds() {
+    fn send<T: Send + ?Sized>() {}
+    fn sync<T: Sync + ?Sized>() {}
+    // Not Sync data can still be moved between threads with the lock
+    send::<MrwLock<std::cell::Cell<i32>>>();
+    send::<MrwLock<[u8]>>();
+    sync::<MrwLock<Vec<i32>>>();
+    send::<ReadGuard<'static, Vec<i32>>>();
+    sync::<WriteGuard<'static, str>>();
+    send::<crate::ArcReadGuard<Vec<i32>, [i32]>>();
+    send::<crate::ArcWriteGuard<dyn std::fmt::Debug + Send + Sync>>();
+    send::<crate::MappedReadGuard<'static, i32>>();
+    sync::<crate::MappedWriteGuard<'static, [u8]>>();
+}
+
+#[test]
+fn release_tokens() {
+    let rwlock = MrwLock::new(vec![1, 2, 3]);
+    let read = rwlock.read_slice().unwrap();
+    let released = read.release();
+    let write = rwlock.write().unwrap();
+    let Err(LockError::WouldBlock(released)) = released.try_reobtain() else {
+        panic!("reobtained while write locked")
+    };
+    drop(write);
+    // The Vec reallocates, the reobtained slice guard must see the new buffer
+    rwlock.write().unwrap().extend(4..100);
+    let read = released.reobtain().unwrap();
+    assert_eq!(read.len(), 99);
+    let write = read.to_write().unwrap();
+    let version = rwlock.version();
+    let released = write.release();
+    assert_eq!(rwlock.version(), version);
+    let mut write = released.reobtain_for(Duration::from_millis(10)).unwrap();
+    write[0] = 0;
+    write.poison("released");
+    let released = write.release();
+    assert_eq!(rwlock.version(), version + 1);
+    let Err(LockError::Poisoned(err)) = released.reobtain() else {
+        panic!()
+    };
+    let write = err.into_inner();
+    assert_eq!(write[..3], [0, 2, 3]);
+    rwlock.clear_poison();
+    let released = write.to_read().release();
+    std::thread::scope(|s| {
+        s.spawn(move || assert_eq!(released.reobtain().unwrap()[1], 2))
+            .join()
+            .unwrap();
+    });
+    assert!(rwlock.try_write().is_ok());
+}
+
+#[test]
+fn unlocked() {
+    let rwlock = MrwLock::new(vec![1, 2, 3]);
+    let mut read = rwlock.read_slice().unwrap();
+    // The Vec reallocates while unlocked
+    let (len, reobtained) = read.unlocked(|| {
+        let mut write = rwlock.write().unwrap();
+        write.extend(4..100);
+        write.len()
+    });
+    assert_eq!(read.len(), len);
+    assert!(reobtained.is_modified());
+    assert_eq!(
+        read.unlocked(|| drop(rwlock.write())).1,
+        Reobtained::Unchanged
+    );
+    let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        read.unlocked(|| {
+            assert!(rwlock.try_write().is_ok());
+            panic!()
+        })
+    }));
+    assert!(panicked.is_err());
+    assert!(matches!(rwlock.try_write(), Err(LockError::WouldBlock(_))));
+    drop(read);
+
+    let mut write = rwlock.write().unwrap();
+    let ((), reobtained) = write.unlocked(|| rwlock.write().unwrap().truncate(1));
+    assert!(reobtained.is_modified());
+    assert!(matches!(rwlock.try_read(), Err(LockError::WouldBlock(_))));
+    write.push(2);
+    drop(write);
+    assert_eq!(*rwlock.read().unwrap(), [1, 2]);
+}
+
+#[test]
+fn unlocked_fair() {
+    for policy in [Policy::ReaderPreferred, Policy::WriterPreferred] {
+        let rwlock = MrwLock::with_policy(0, policy);
+        let mut read = rwlock.read().unwrap();
+        std::thread::scope(|s| {
+            s.spawn(|| *rwlock.write().unwrap() += 1);
+            while rwlock.state.waiting.load(Relaxed) == 0 {
+                std::thread::yield_now();
+            }
+            // Without the hand off this thread would usually take the read lock straight back
+            read.unlocked_fair(|| ());
+            assert_eq!(*read, 1);
+            drop(read);
+            let mut write = rwlock.write().unwrap();
+            let reader = s.spawn(|| *rwlock.read().unwrap());
+            while rwlock.state.waiting.load(Relaxed) == 0 {
+                std::thread::yield_now();
+            }
+            write.unlocked_fair(|| ());
+            *write += 1;
+            drop(write);
+            assert_eq!(reader.join().unwrap(), 1);
+        });
+        assert_eq!(rwlock.state.waiting.load(Relaxed), 0);
+        assert_eq!(*rwlock.read().unwrap(), 2);
+    }
+}
+
+#[test]
+fn reobtained() {
+    let rwlock = Arc::new(MrwLock::new((0, vec![1])));
+    let read = rwlock.read().unwrap();
+    unsafe { read.early_release() };
+    assert_eq!(unsafe { read.reobtain() }.unwrap(), Reobtained::Unchanged);
+    unsafe { read.early_release() };
+    rwlock.write().unwrap().1.push(2);
+    assert_eq!(
+        unsafe { read.try_reobtain() }.unwrap(),
+        Reobtained::Modified
+    );
+    drop(read);
+
+    // A write guard's own changes are published on release but not reported back to it
+    let mut write = rwlock.write().unwrap();
+    write.0 += 1;
+    unsafe { write.early_release() };
+    assert_eq!(
+        unsafe { write.reobtain_for(Duration::from_secs(1)) }.unwrap(),
+        Reobtained::Unchanged
+    );
+    unsafe { write.early_release() };
+    drop(rwlock.read().unwrap());
+    drop(rwlock.write().unwrap());
+    assert_eq!(unsafe { write.reobtain() }.unwrap(), Reobtained::Unchanged);
+    let count = write.map(|(count, _)| count);
+    unsafe { count.early_release() };
+    rwlock.write().unwrap().1.clear();
+    assert!(unsafe { count.reobtain() }.unwrap().is_modified());
+    let read = count.to_read();
+    unsafe { read.early_release() };
+    rwlock.write().unwrap().0 += 1;
+    assert!(unsafe { read.reobtain() }.unwrap().is_modified());
+    assert_eq!(*read, 2);
+    drop(read);
+
+    let arc_read = rwlock.read_arc().unwrap();
+    unsafe { arc_read.early_release() };
+    let mut write = rwlock.write().unwrap();
+    write.0 = 0;
+    write.poison("bad");
+    drop(write);
+    let Err(LockError::Poisoned(err)) = (unsafe { arc_read.reobtain() }) else {
+        panic!("reobtain did not report poison")
+    };
+    assert_eq!(err.into_inner(), Reobtained::Modified);
+}
+
+#[test]
+fn optimistic_read() {
+    let rwlock = MrwLock::new((0u64, 0u64));
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for i in 1..=1000 {
+                *rwlock.write().unwrap() = (i, i);
+            }
+        });
+        let mut last = 0;
+        while last < 1000 {
+            let (a, b) = rwlock.optimistic_read(|pair| *pair);
+            assert_eq!(a, b);
+            assert!(a >= last);
+            last = a;
+        }
+    });
+    // Readers never touch the state word
+    assert_eq!(rwlock.state.state.load(Relaxed), 0);
+
+    // A write lock that has not modified the data does not get in the way
+    let write = rwlock.write().unwrap();
+    assert_eq!(rwlock.optimistic_read(|pair| pair.0), 1000);
+    drop(write);
+
+    // Half finished modifications are never seen, readers fall back to waiting for the lock
+    let mut write = rwlock.write().unwrap();
+    write.0 = 0;
+    std::thread::scope(|s| {
+        let reader = s.spawn(|| rwlock.optimistic_read(|pair| *pair));
+        std::thread::sleep(Duration::from_millis(20));
+        write.1 = 0;
+        drop(write);
+        assert_eq!(reader.join().unwrap(), (0, 0));
+    });
+}
+
+#[test]
+fn seq_cell() {
+    let cell = SeqCell::new([0u32; 8]);
+    std::thread::scope(|s| {
+        for _ in 0..2 {
+            s.spawn(|| {
+                for _ in 0..500 {
+                    cell.update(|values| values.map(|v| v + 1));
+                }
+            });
+        }
+        loop {
+            let values = cell.get();
+            assert!(values.iter().all(|&v| v == values[0]));
+            if values[0] == 1000 {
+                break;
+            }
+        }
+    });
+    assert_eq!(cell.replace([1; 8]), [1000; 8]);
+
+    // A panicking update still ends the write
+    let panicked =
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| cell.update(|_| panic!())));
+    assert!(panicked.is_err());
+    cell.set([2; 8]);
+    assert_eq!(cell.into_inner(), [2; 8]);
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn stats() {
+    let rwlock = MrwLock::new(vec![1]);
+    let read = rwlock.read().unwrap();
+    // Another reader is still there, the clone handed back by the failed conversion is dropped
+    assert!(matches!(
+        read.clone().try_to_write(),
+        Err(LockError::WouldBlock(_))
+    ));
+    let mut write = read.to_write().unwrap();
+    std::thread::scope(|s| {
+        let reader = s.spawn(|| rwlock.read().unwrap().len());
+        while rwlock.state.waiting.load(Relaxed) == 0 {
+            std::thread::yield_now();
+        }
+        std::thread::sleep(Duration::from_millis(10));
+        write.push(2);
+        drop(write);
+        assert_eq!(reader.join().unwrap(), 2);
+    });
+    let upgradable = rwlock.upgradable_read().unwrap();
+    let write = upgradable.upgrade();
+    unsafe { write.early_release() };
+    unsafe { write.reobtain().unwrap() };
+    drop(write);
+
+    let stats = rwlock.stats();
+    // Only the reobtained write lock is a write, the others were upgrades
+    assert_eq!(
+        (stats.reads, stats.upgradable_reads, stats.writes),
+        (3, 1, 1)
+    );
+    assert_eq!((stats.upgrades, stats.failed_upgrades), (2, 1));
+    assert_eq!((stats.early_releases, stats.reobtains), (1, 1));
+    // The failed upgrade did not count, only the reader waiting for the writer
+    assert_eq!(stats.contended, 1);
+    assert!(stats.max_wait >= Duration::from_millis(10));
+    assert!(stats.max_write_hold >= Duration::from_millis(10));
+    assert!(stats.max_read_hold > Duration::ZERO);
+
+    rwlock.reset_stats();
+    assert_eq!(rwlock.stats(), Default::default());
+    drop(rwlock.write());
+    assert_eq!(rwlock.stats().writes, 1);
+}
+
+#[cfg(feature = "deadlock_detection")]
+#[test]
+fn deadlock_detection() {
+    use crate::{check_deadlock, DeadlockedThread};
+    use std::{sync::mpsc, thread::ThreadId};
+
+    let a = Arc::new(MrwLock::new(0));
+    let b = Arc::new(MrwLock::new(0));
+    let (released_tx, released_rx) = mpsc::channel();
+    let (locked_tx, locked_rx) = mpsc::channel();
+    let t1 = {
+        let (a, b) = (a.clone(), b.clone());
+        std::thread::spawn(move || {
+            let read = a.read().unwrap();
+            unsafe { read.early_release() };
+            let _b = b.write().unwrap();
+            released_tx.send(()).unwrap();
+            locked_rx.recv().unwrap();
+            // Blocks on the write lock taken while this guard was released
+            let _ = unsafe { read.reobtain() };
+        })
+    };
+    let t2 = std::thread::spawn(move || {
+        released_rx.recv().unwrap();
+        let _a = a.write().unwrap();
+        locked_tx.send(()).unwrap();
+        let _b = b.write().unwrap();
+    });
+    // Upgrading waits for the other read lock held by the same thread
+    let c = Arc::new(MrwLock::new(0));
+    let t3 = std::thread::spawn(move || {
+        let read = c.read().unwrap();
+        let _write = read.clone().to_write();
+    });
+
+    // A second read lock waits behind a queued writer, unless the policy prefers readers
+    let mut recursive = Vec::new();
+    for policy in [
+        Policy::ReaderPreferred,
+        Policy::WriterPreferred,
+        Policy::PhaseFair,
+        Policy::Fifo,
+    ] {
+        let d = Arc::new(MrwLock::with_policy(0, policy));
+        let (read_tx, read_rx) = mpsc::channel();
+        let reader = {
+            let d = d.clone();
+            std::thread::spawn(move || {
+                let _read = d.read().unwrap();
+                read_tx.send(()).unwrap();
+                while d.state.waiting.load(Relaxed) == 0 {
+                    std::thread::yield_now();
+                }
+                drop(d.read().unwrap());
+            })
+        };
+        read_rx.recv().unwrap();
+        let writer = std::thread::spawn(move || drop(d.write().unwrap()));
+        if policy == Policy::ReaderPreferred {
+            reader.join().unwrap();
+            writer.join().unwrap();
+        } else {
+            recursive.push([reader.thread().id(), writer.thread().id()]);
+        }
+    }
+
+    let ids = |cycle: &[DeadlockedThread]| {
+        cycle
+            .iter()
+            .map(|t| t.thread_id())
+            .collect::<Vec<ThreadId>>()
+    };
+    let (t1, t2, t3) = (t1.thread().id(), t2.thread().id(), t3.thread().id());
+    let mut found = (false, false);
+    let start = Instant::now();
+    while found != (true, true) || !recursive.is_empty() {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "deadlocks not found"
+        );
+        for cycle in check_deadlock() {
+            let ids = ids(&cycle);
+            if ids.contains(&t1) {
+                assert_eq!(ids.len(), 2);
+                assert!(ids.contains(&t2));
+                found.0 = true;
+            }
+            if ids == [t3] {
+                found.1 = true;
+            }
+            recursive.retain(|pair| !(ids.len() == 2 && pair.iter().all(|id| ids.contains(id))));
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[cfg(feature = "deadlock_detection")]
+#[test]
+fn overlapping_deadlocks() {
+    use crate::check_deadlock;
+    use std::sync::Barrier;
+
+    // Each thread read locks the other two locks, then waits to write its own which both of them hold
+    let locks = Arc::new(std::array::from_fn::<_, 3, _>(|_| MrwLock::new(0)));
+    let barrier = Arc::new(Barrier::new(3));
+    let threads: Vec<_> = (0..3)
+        .map(|i| {
+            let (locks, barrier) = (locks.clone(), barrier.clone());
+            std::thread::spawn(move || {
+                let _reads: Vec<_> = (0..3)
+                    .filter(|&j| j != i)
+                    .map(|j| locks[j].read().unwrap())
+                    .collect();
+                barrier.wait();
+                let _write = locks[i].write();
+            })
+        })
+        .collect();
+    let ids: Vec<_> = threads.iter().map(|t| t.thread().id()).collect();
+
+    // Every pair is a cycle, and all three are in both directions
+    let start = Instant::now();
+    loop {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "deadlocks not found"
+        );
+        let cycles: Vec<_> = check_deadlock()
+            .into_iter()
+            .filter(|cycle| cycle.iter().all(|t| ids.contains(&t.thread_id())))
+            .collect();
+        let lengths = |len| cycles.iter().filter(|cycle| cycle.len() == len).count();
+        if (lengths(2), lengths(3)) == (3, 2) {
+            assert_eq!(cycles.len(), 5);
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn lock_hierarchy() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    let low = MrwLock::with_level(1, 1);
+    let high = MrwLock::builder().level(2).build(vec![2]);
+    let panics = |f: &dyn Fn()| catch_unwind(AssertUnwindSafe(f)).is_err();
+
+    let read = low.read().unwrap();
+    let write = high.write().unwrap();
+    assert!(panics(&|| drop(low.read())));
+    // Same level as a held lock
+    assert!(panics(&|| drop(high.read_slice_for::<i32>(Duration::ZERO))));
+    // Can not deadlock
+    assert!(low.try_read().is_ok());
+    drop(write);
+
+    // Released guards are not held, but are checked when reobtained
+    unsafe { read.early_release() };
+    let write = high.write().unwrap();
+    assert!(panics(&|| drop(unsafe { read.reobtain() })));
+    drop(write);
+    unsafe { read.reobtain() }.unwrap();
+    let clone = read.clone();
+    drop(read);
+    assert!(panics(&|| drop(low.write_for(Duration::ZERO))));
+    drop(clone);
+
+    // `unlocked` only checks once the lock is held again, and not at all while unwinding as that would abort
+    let mut read = low.read().unwrap();
+    let write = high.write().unwrap();
+    assert!(catch_unwind(AssertUnwindSafe(|| read.unlocked(|| ()))).is_err());
+    assert!(catch_unwind(AssertUnwindSafe(|| read.unlocked(|| panic!()))).is_err());
+    assert!(low.try_write().is_err());
+    drop((read, write));
+    assert!(low.try_write().is_ok());
+
+    // Unlevelled locks are never checked
+    let other = MrwLock::new(0);
+    let _other = other.write().unwrap();
+    drop(high.read().unwrap());
+
+    // A guard dropped on another thread removes its own hold, not that thread's
+    let read = high.read().unwrap();
+    let moved = read.clone();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let _theirs = high.read().unwrap();
+            drop(moved);
+            assert!(panics(&|| drop(low.read())));
+        });
+    });
+    drop(read);
+    drop(low.read().unwrap());
+
+    // Arc guards are not counted as held by the thread that obtained them, or the one they are moved to
+    let high = Arc::new(high);
+    let write = high.write_arc().unwrap();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let _write = write;
+            drop(low.read().unwrap());
+        });
+    });
+    let read = high.read_arc().unwrap();
+    let clone = read.clone();
+    std::thread::spawn(move || drop(clone)).join().unwrap();
+    drop(low.read().unwrap());
+    drop(read);
+}
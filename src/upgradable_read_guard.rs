@@ -0,0 +1,79 @@
+use std::ops::Deref;
+
+use crate::{hierarchy::Held, LockState, ReadGuard, WriteGuard};
+
+/// # Upgradable Read Guard
+/// A read guard that is guaranteed to be able to upgrade to a [WriteGuard].
+/// Only one can exist per lock, but it does not block normal readers until [Self::upgrade] is called
+///
+/// # Examples
+/// ```
+/// use manual_rwlock::MrwLock;
+/// let rwlock = MrwLock::new(5);
+/// let upgradable = rwlock.upgradable_read().unwrap();
+/// let read = rwlock.read().unwrap();
+/// assert_eq!(*upgradable, *read);
+/// drop(read);
+/// let mut write = upgradable.upgrade();
+/// *write += 1;
+/// assert_eq!(*write, 6)
+/// ```
+pub struct UpgradableReadGuard<'a, T: ?Sized> {
+    pub(super) state: &'a LockState,
+    pub(super) data: *mut T,
+    pub(super) held: Held,
+}
+
+impl<'a, T: ?Sized> UpgradableReadGuard<'a, T> {
+    /// Wrap an upgradable read lock already held on `state`, it is released when the guard is dropped
+    pub(crate) fn new(state: &'a LockState, data: *mut T) -> Self {
+        UpgradableReadGuard {
+            state,
+            data,
+            held: Held::new(state),
+        }
+    }
+
+    /// Block new readers and wait for the existing ones to leave, then convert to a write guard
+    pub fn upgrade(self) -> WriteGuard<'a, T> {
+        self.state.upgrade();
+        let write = WriteGuard::converted(self.state, self.data, None, self.held.take());
+        std::mem::forget(self);
+        write
+    }
+
+    /// Convert to a write guard if there are no other readers, otherwise give the guard back
+    pub fn try_upgrade(self) -> Result<WriteGuard<'a, T>, Self> {
+        if self.state.try_upgrade().is_err() {
+            return Err(self);
+        }
+        let write = WriteGuard::converted(self.state, self.data, None, self.held.take());
+        std::mem::forget(self);
+        Ok(write)
+    }
+
+    /// Convert to a normal read guard, allowing another upgradable read guard to be obtained
+    pub fn downgrade(self) -> ReadGuard<'a, T> {
+        self.state.downgrade_upgradable();
+        let read = ReadGuard::converted(self.state, self.data, None, self.held.take());
+        std::mem::forget(self);
+        read
+    }
+}
+
+impl<'a, T: ?Sized> Drop for UpgradableReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.state.drop_upgradable();
+    }
+}
+
+impl<'a, T: ?Sized> Deref for UpgradableReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.data }
+    }
+}
+
+unsafe impl<'a, T: ?Sized + Send + Sync> Send for UpgradableReadGuard<'a, T> {}
+unsafe impl<'a, T: ?Sized + Sync> Sync for UpgradableReadGuard<'a, T> {}
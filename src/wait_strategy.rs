@@ -0,0 +1,78 @@
+use crate::{
+    futex::wait_until,
+    sync::{spin_loop, yield_now, AtomicU32},
+};
+use std::time::Instant;
+
+/// How a thread waits for a [LockState](crate::LockState) that it can not obtain yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitStrategy {
+    /// Park the thread with a futex wait straight away
+    Park,
+    /// Spin `spins` times, then yield to the scheduler `yields` times, then park.
+    /// Avoids a syscall for locks that are only held for a short time
+    Adaptive { spins: u32, yields: u32 },
+    /// Never park, only spin. Lowest latency for very short critical sections, but burns a core while waiting.
+    /// Only suitable when every contending thread has a core to itself
+    Spin,
+}
+
+impl WaitStrategy {
+    pub const DEFAULT: WaitStrategy = WaitStrategy::Adaptive {
+        spins: 64,
+        yields: 4,
+    };
+
+    /// Whether waiting threads may be parked and so need waking
+    pub(crate) fn parks(self) -> bool {
+        self != WaitStrategy::Spin
+    }
+}
+
+impl Default for WaitStrategy {
+    fn default() -> Self {
+        WaitStrategy::DEFAULT
+    }
+}
+
+/// Progress through a [WaitStrategy] over one acquisition
+pub(crate) struct Backoff {
+    strategy: WaitStrategy,
+    step: u32,
+}
+
+impl Backoff {
+    pub(crate) fn new(strategy: WaitStrategy) -> Backoff {
+        Backoff { strategy, step: 0 }
+    }
+
+    /// Wait for `atomic` to change from `value`, returning false if `deadline` has passed.
+    /// While spinning or yielding this returns straight away, so the caller must recheck the state and call again
+    pub(crate) fn wait(
+        &mut self,
+        atomic: &AtomicU32,
+        value: u32,
+        deadline: Option<Instant>,
+    ) -> bool {
+        if self.strategy == WaitStrategy::Park {
+            return wait_until(atomic, value, deadline);
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return false;
+        }
+        match self.strategy {
+            WaitStrategy::Adaptive { spins, yields } => {
+                if self.step < spins {
+                    spin_loop();
+                } else if self.step < spins.saturating_add(yields) {
+                    yield_now();
+                } else {
+                    return wait_until(atomic, value, deadline);
+                }
+                self.step += 1;
+            }
+            _ => spin_loop(),
+        }
+        true
+    }
+}
@@ -0,0 +1,73 @@
+//! Wakers of async tasks waiting for a [LockState](crate::LockState)
+use crate::sync::{loom_const_fn, AtomicU32, Mutex, MutexGuard};
+use std::{sync::atomic::Ordering::AcqRel, task::Waker};
+
+struct WakerList {
+    next_key: u32,
+    wakers: Vec<(u32, Waker)>,
+}
+
+/// Every waiting task is woken whenever the lock may have become available, so a task that is
+/// dropped after being woken can not swallow the wake up of another task.
+/// `bit` is set in the word the tasks wait on while any are registered, changes to it then wake them
+pub(crate) struct Wakers {
+    list: Mutex<WakerList>,
+    bit: u32,
+}
+
+impl Wakers {
+    loom_const_fn! {
+        pub(crate) fn new(bit: u32) -> Wakers {
+            Wakers {
+                list: Mutex::new(WakerList {
+                    next_key: 0,
+                    wakers: Vec::new(),
+                }),
+                bit,
+            }
+        }
+    }
+
+    fn list(&self) -> MutexGuard<'_, WakerList> {
+        self.list.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Register `waker` under `key`, replacing the waker from an earlier poll, then set the bit on `word`.
+    /// Any change after this sees the bit and wakes the task
+    pub(crate) fn register(&self, key: &mut Option<u32>, waker: &Waker, word: &AtomicU32) {
+        let mut list = self.list();
+        let k = match *key {
+            Some(k) => k,
+            None => {
+                let k = list.next_key;
+                list.next_key = k.wrapping_add(1);
+                *key = Some(k);
+                k
+            }
+        };
+        match list.wakers.iter_mut().find(|(key, _)| *key == k) {
+            Some((_, registered)) => registered.clone_from(waker),
+            None => list.wakers.push((k, waker.clone())),
+        }
+        word.fetch_or(self.bit, AcqRel);
+    }
+
+    /// Remove the waker registered under `key`, if it was not already woken
+    pub(crate) fn remove(&self, key: &mut Option<u32>) {
+        if let Some(k) = key.take() {
+            self.list().wakers.retain(|(key, _)| *key != k);
+        }
+    }
+
+    /// Clear the bit on `word` and wake every registered task
+    pub(crate) fn wake_all(&self, word: &AtomicU32) {
+        let wakers = {
+            let mut list = self.list();
+            word.fetch_and(!self.bit, AcqRel);
+            std::mem::take(&mut list.wakers)
+        };
+        for (_, waker) in wakers {
+            waker.wake();
+        }
+    }
+}
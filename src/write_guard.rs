@@ -1,20 +1,143 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use crate::{LockResult, LockState, ReadGuard};
+use crate::{
+    deadlock::Want, hierarchy::Held, reobtained::ReleasedAt, LockResult, LockState,
+    MappedWriteGuard, ReadGuard, ReleasedWrite, Reobtained, Source,
+};
 
-pub struct WriteGuard<'a, T: Sized> {
+pub struct WriteGuard<'a, T: ?Sized> {
     pub(super) state: &'a LockState,
     pub(super) data: *mut T,
+    pub(super) source: Option<Source<T>>,
+    pub(super) released_at: ReleasedAt,
+    pub(super) held: Held,
 }
 
-impl<'a, T> WriteGuard<'a, T> {
+impl<'a, T: ?Sized> WriteGuard<'a, T> {
+    /// Wrap a write lock already held on `state`, it is released when the guard is dropped
+    pub(crate) fn new(state: &'a LockState, data: *mut T, source: Option<Source<T>>) -> Self {
+        WriteGuard::converted(state, data, source, Held::new(state))
+    }
+
+    /// Same as [Self::new] for a lock taken over from a converted guard, along with its record of holding it
+    pub(crate) fn converted(
+        state: &'a LockState,
+        data: *mut T,
+        source: Option<Source<T>>,
+        held: Held,
+    ) -> Self {
+        WriteGuard {
+            state,
+            data,
+            source,
+            released_at: ReleasedAt::new(),
+            held,
+        }
+    }
+
     /// Convert to a read guard. This should always work as having a write lock guarantees there is only one lock
     pub fn to_read(self) -> ReadGuard<'a, T> {
         self.state.to_read();
-        ReadGuard {
-            state: &self.state,
+        // Other readers may borrow slices from now on, so stop using the mutable borrow
+        let data = self
+            .source
+            .map_or(self.data, |source| unsafe { source.find() });
+        let read = ReadGuard::converted(self.state, data, self.source, self.held.take());
+        std::mem::forget(self);
+        read
+    }
+
+    /// Make a guard pointing to part of the data, such as a field, which keeps holding the write lock
+    /// ```
+    /// use manual_rwlock::MrwLock;
+    /// let mrw_lock = MrwLock::new((1, String::from("one")));
+    /// let mut name = mrw_lock.write().unwrap().map(|(_, name)| name);
+    /// name.push('s');
+    /// drop(name);
+    /// assert_eq!(mrw_lock.read().unwrap().1, "ones");
+    /// ```
+    pub fn map<U: ?Sized>(self, f: impl FnOnce(&mut T) -> &mut U) -> MappedWriteGuard<'a, U> {
+        self.state.mark_modified();
+        let data = f(unsafe { &mut *self.data }) as *mut U;
+        let (state, held) = (self.state, self.held.take());
+        std::mem::forget(self);
+        MappedWriteGuard::converted(state, data, held)
+    }
+
+    /// Same as [Self::map] but `f` can fail by returning `None`, in which case this guard is handed back.
+    /// Only a successful projection counts as modifying the data, so `f` should not modify it before returning `None`
+    pub fn try_map<U: ?Sized>(
+        self,
+        f: impl FnOnce(&mut T) -> Option<&mut U>,
+    ) -> Result<MappedWriteGuard<'a, U>, Self> {
+        // Optimistic readers still have to treat `f` as a write
+        let marked = self.state.try_mark_modified();
+        let Some(data) = f(unsafe { &mut *self.data }).map(|data| data as *mut U) else {
+            if marked {
+                self.state.unmark_modified();
+            }
+            return Err(self);
+        };
+        let (state, held) = (self.state, self.held.take());
+        std::mem::forget(self);
+        Ok(MappedWriteGuard::converted(state, data, held))
+    }
+
+    /// Poison the lock, recording `reason`. Use when the data was left in an invalid state without panicking.
+    /// Later locks return [LockError::Poisoned](crate::LockError::Poisoned) carrying the reason until it is cleared,
+    /// a panic while still holding the lock does not replace it
+    pub fn poison(&self, reason: impl Into<Arc<str>>) {
+        self.state.poison(reason);
+    }
+
+    /// Release the lock, giving a token with no access to the data which can later reobtain it. Safe alternative to [Self::early_release]
+    pub fn release(self) -> ReleasedWrite<'a, T> {
+        self.held.release();
+        self.state.stats.early_released();
+        self.state.drop_write();
+        let released = ReleasedWrite {
+            state: self.state,
             data: self.data,
-        }
+            source: self.source,
+        };
+        std::mem::forget(self);
+        released
+    }
+
+    /// Release the lock while `f` runs, reobtaining it before returning even if `f` panics.
+    /// Returns the result of `f` and whether the data was modified while it ran.
+    /// If the lock is poisoned in the meantime it is still reobtained, see [MrwLock::is_poisoned](crate::MrwLock::is_poisoned)
+    /// ```
+    /// use manual_rwlock::MrwLock;
+    /// let mrw_lock = MrwLock::new(vec![1]);
+    /// let mut write = mrw_lock.write().unwrap();
+    /// write.unlocked(|| mrw_lock.write().unwrap().push(2));
+    /// write.push(3);
+    /// assert_eq!(*write, [1, 2, 3]);
+    /// ```
+    pub fn unlocked<R>(&mut self, f: impl FnOnce() -> R) -> (R, Reobtained) {
+        self.held.release();
+        self.released_at.release_write(self.state);
+        self.state.drop_write();
+        let relock = Relock(self, None);
+        let res = f();
+        (res, relock.finish())
+    }
+
+    /// Same as [Self::unlocked] but threads waiting when the lock was released get it before it is reobtained, see [ReadGuard::unlocked_fair]
+    pub fn unlocked_fair<R>(&mut self, f: impl FnOnce() -> R) -> (R, Reobtained) {
+        let handoff = self.state.handoff();
+        self.held.release();
+        self.released_at.release_write(self.state);
+        self.state.drop_write();
+        let relock = Relock(self, Some(handoff));
+        let res = f();
+        (res, relock.finish())
     }
 
     /// Releases lock without dropping object. This can allow for a write lock to obtained and do some work after which the lock must be reobtained
@@ -38,33 +161,90 @@ impl<'a, T> WriteGuard<'a, T> {
     ///```
     ///
     pub unsafe fn early_release(&self) {
+        self.held.release();
+        self.released_at.release_write(self.state);
         self.state.drop_write();
     }
 
-    /// block until lock can be reobtained
-    /// # Safety 
+    /// block until lock can be reobtained, returning whether the data was modified while released.
+    /// If the lock is poisoned it is still reobtained and [LockError::Poisoned](crate::LockError::Poisoned) is returned
+    /// # Safety
     /// do not use unless early release has been called. Only call at most once after each early release
-    pub unsafe fn reobtain(&self) -> LockResult<()> {
-        self.state.write()?;
-        Ok(())
+    pub unsafe fn reobtain(&self) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.write())
+    }
+
+    /// Same as [Self::reobtain] but gives up with [LockError::TimedOut](crate::LockError::TimedOut) after `timeout`
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain_for(&self, timeout: Duration) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.write_for(timeout))
+    }
+
+    /// Same as [Self::reobtain] but gives up with [LockError::TimedOut](crate::LockError::TimedOut) once `deadline` has passed
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub unsafe fn reobtain_until(&self, deadline: Instant) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.write_until(deadline))
+    }
+
+    /// Same as [Self::reobtain] but waits without blocking the thread.
+    /// If the future is dropped before it completes the lock is not reobtained
+    /// # Safety
+    /// do not use unless early release has been called. Only call at most once after each early release
+    pub async unsafe fn reobtain_async(&self) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.write_async().await)
     }
 
     /// attempt to reobtain lock, if not possible at this time return `LockError:WouldBlock`
-    /// # Safety 
+    /// # Safety
     /// do not use unless early release has been called. Only call at most once after each early release
-    pub unsafe fn try_reobtain(&self) -> LockResult<()> {
-        self.state.try_write()?;
-        Ok(())
+    pub unsafe fn try_reobtain(&self) -> LockResult<Reobtained> {
+        self.released_at
+            .reobtained(self.state, &self.held, self.state.try_write())
+    }
+}
+
+/// Reobtains the write lock when dropped, waiting for the hand off first if there is one, see [WriteGuard::unlocked]
+struct Relock<'g, 'a, T: ?Sized>(&'g mut WriteGuard<'a, T>, Option<(u32, u32)>);
+
+impl<T: ?Sized> Relock<'_, '_, T> {
+    fn relock(&mut self) -> Reobtained {
+        let guard = &mut *self.0;
+        if let Some(handoff) = self.1 {
+            guard.state.wait_for_handoff(handoff);
+        }
+        guard.state.relock(Want::Write);
+        if let Some(source) = guard.source {
+            guard.data = unsafe { source.find_mut() };
+        }
+        guard.held.record(guard.state);
+        guard.released_at.relocked(guard.state)
+    }
+
+    /// Reobtain the lock once `f` has returned
+    fn finish(self) -> Reobtained {
+        ManuallyDrop::new(self).relock()
+    }
+}
+
+impl<T: ?Sized> Drop for Relock<'_, '_, T> {
+    fn drop(&mut self) {
+        self.relock();
     }
 }
 
-impl<'a, T> Drop for WriteGuard<'a, T> {
+impl<'a, T: ?Sized> Drop for WriteGuard<'a, T> {
     fn drop(&mut self) {
         self.state.drop_write();
     }
 }
 
-impl<'a, T> Deref for WriteGuard<'a, T> {
+impl<'a, T: ?Sized> Deref for WriteGuard<'a, T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -72,11 +252,33 @@ impl<'a, T> Deref for WriteGuard<'a, T> {
     }
 }
 
-impl<'a, T> DerefMut for WriteGuard<'a, T> {
+impl<'a, T: ?Sized> DerefMut for WriteGuard<'a, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
+        self.state.mark_modified();
         unsafe { &mut *self.data }
     }
 }
 
-unsafe impl<'a,T> Send for WriteGuard<'a, T>{}
-unsafe impl<'a, T> Sync for WriteGuard<'a, T>{}
\ No newline at end of file
+/// Sending a write guard also needs `T: Sync`, as it can be converted to a read guard alongside readers on other threads
+/// ```compile_fail
+/// use manual_rwlock::MrwLock;
+/// use std::cell::Cell;
+/// let mrw_lock = MrwLock::new(Cell::new(0));
+/// let write = mrw_lock.write().unwrap();
+/// std::thread::scope(|s| {
+///     s.spawn(move || write.to_read().set(1));
+///     mrw_lock.read().unwrap().set(2);
+/// });
+/// ```
+/// ```compile_fail
+/// use manual_rwlock::MrwLock;
+/// use std::rc::Rc;
+/// let mrw_lock = MrwLock::new(Rc::new(0));
+/// let mut write = mrw_lock.write().unwrap();
+/// let rc = write.clone();
+/// std::thread::scope(|s| {
+///     s.spawn(move || *write = Rc::new(1));
+/// });
+/// ```
+unsafe impl<'a, T: ?Sized + Send + Sync> Send for WriteGuard<'a, T> {}
+unsafe impl<'a, T: ?Sized + Sync> Sync for WriteGuard<'a, T> {}